@@ -0,0 +1,452 @@
+#![warn(missing_docs)]
+
+//! Procedural macros for [miniactor](https://docs.rs/miniactor).
+//! They are re-exported by `miniactor` with the `macros` feature, which is enabled by default.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::{
+    parse_macro_input, FnArg, GenericArgument, Ident, ImplItem, ItemImpl, PathArguments,
+    ReturnType, Type, Visibility,
+};
+
+/// Turn an `impl` block of handler methods into an actor.
+///
+/// Every `async fn` taking `self` becomes a message. The macro generates
+///
+/// - a message enum named after the actor with a `Msg` suffix, holding one variant per method,
+/// - the `Actor` implementation dispatching each variant to its method and sending back the
+///   returned value,
+/// - a `<Actor>HandleExt` trait implemented for `Handle<Msg>` with one method per handler that
+///   sends the message and waits for the reply.
+///
+/// Methods that are not `async` are left untouched and can be used as helpers.
+/// Handlers cannot be named after a method of `Handle`, such as `send`, `stop` or `ask`,
+/// since the `Handle` method would be called instead.
+/// A handler can take the actor's `Context` by declaring a `&mut Context<Self>` parameter.
+///
+/// The macro accepts three optional arguments:
+///
+/// - `msg = Name` names the message enum,
+/// - `vis = pub(crate)` sets the visibility of the message enum and the extension trait,
+///   they are `pub` by default,
+/// - `error = Type` sets `Actor::Error`, handlers then return `Result<T, E>` where `E` converts
+///   into the error, and an error is handled by the actor's `ErrorPolicy`.
+///   Without it the error type is `Infallible`.
+///
+/// ```rust
+/// use miniactor::{actor, Context, Handle};
+///
+/// pub struct Counter {
+///     count: u32,
+/// }
+///
+/// #[actor]
+/// impl Counter {
+///     async fn add(&mut self, n: u32) -> u32 {
+///         self.count += n;
+///         self.count
+///     }
+///
+///     async fn reset(&mut self, ctx: &mut Context<Self>) {
+///         self.count = 0;
+///         ctx.stop();
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let h = Handle::new(Counter { count: 0 });
+///     assert_eq!(h.add(2).await, Ok(2));
+///     assert_eq!(h.add(3).await, Ok(5));
+///     h.send(CounterMsg::Add(1, None)).await.unwrap();
+///     h.reset().await.unwrap();
+///     h.stopped().await;
+/// }
+/// ```
+///
+/// With an error type the handlers return a `Result`:
+///
+/// ```rust
+/// use miniactor::{actor, AskError, Builder, ErrorPolicy};
+///
+/// struct Parser;
+///
+/// #[actor(msg = ParseRequest, error = std::num::ParseIntError, vis = pub(crate))]
+/// impl Parser {
+///     async fn parse(&mut self, text: String) -> Result<u32, std::num::ParseIntError> {
+///         text.parse()
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let h = Builder::new(Parser).error_policy(ErrorPolicy::Ignore).spawn();
+///     assert_eq!(h.parse("12".to_string()).await, Ok(12));
+///     assert_eq!(h.parse("twelve".to_string()).await, Err(AskError::Dropped));
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn actor(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut args = Args::default();
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("msg") {
+            args.msg = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("error") {
+            args.error = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("vis") {
+            args.vis = Some(meta.value()?.parse()?);
+            Ok(())
+        } else {
+            Err(meta.error("expected `msg`, `error` or `vis`"))
+        }
+    });
+    parse_macro_input!(attr with parser);
+    let item = parse_macro_input!(item as ItemImpl);
+    expand(args, item)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[derive(Default)]
+struct Args {
+    msg: Option<Ident>,
+    error: Option<Type>,
+    vis: Option<Visibility>,
+}
+
+/// A handler method turned into a message variant.
+struct Handler {
+    method: Ident,
+    variant: Ident,
+    /// Types of the message arguments, without the context.
+    args: Vec<Type>,
+    /// Position of the context among the method's parameters.
+    ctx: Option<usize>,
+    reply: Type,
+}
+
+fn expand(args: Args, item: ItemImpl) -> syn::Result<TokenStream2> {
+    if let Some((_, path, _)) = &item.trait_ {
+        return Err(syn::Error::new_spanned(
+            path,
+            "#[actor] expects an inherent impl block",
+        ));
+    }
+    if !item.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &item.generics,
+            "#[actor] does not support generic actors",
+        ));
+    }
+    let actor = match &*item.self_ty {
+        Type::Path(path) => &path.path.segments.last().unwrap().ident,
+        ty => return Err(syn::Error::new_spanned(ty, "expected an actor type")),
+    };
+    let msg = args.msg.unwrap_or_else(|| format_ident!("{}Msg", actor));
+    let ext = format_ident!("{}HandleExt", actor);
+    let vis = args.vis.unwrap_or_else(|| syn::parse_quote!(pub));
+
+    let mut handlers = Vec::new();
+    for item in &item.items {
+        if let ImplItem::Fn(method) = item {
+            if method.sig.asyncness.is_some() && method.sig.receiver().is_some() {
+                handlers.push(handler(method, args.error.is_some())?);
+            }
+        }
+    }
+
+    let variants = handlers.iter().map(|h| {
+        let variant = &h.variant;
+        let args = &h.args;
+        let reply = &h.reply;
+        let doc = format!("Message for `{}::{}`.", actor, h.method);
+        quote! {
+            #[doc = #doc]
+            #variant(#(#args,)* ::std::option::Option<::miniactor::Reply<#reply>>)
+        }
+    });
+
+    let try_op = args.error.as_ref().map(|_| quote!(?));
+    let arms = handlers.iter().map(|h| {
+        let method = &h.method;
+        let variant = &h.variant;
+        let names: Vec<_> = (0..h.args.len())
+            .map(|i| format_ident!("arg{}", i))
+            .collect();
+        let mut call: Vec<TokenStream2> = names.iter().map(|name| quote!(#name)).collect();
+        if let Some(pos) = h.ctx {
+            call.insert(pos, quote!(ctx));
+        }
+        quote! {
+            #msg::#variant(#(#names,)* reply) => {
+                let value = self.#method(#(#call),*).await #try_op;
+                if let ::std::option::Option::Some(reply) = reply {
+                    reply.send(value);
+                }
+            }
+        }
+    });
+
+    let error = match &args.error {
+        Some(error) => quote!(#error),
+        None => quote!(::std::convert::Infallible),
+    };
+
+    let signatures: Vec<_> = handlers
+        .iter()
+        .map(|h| {
+            let method = &h.method;
+            let reply = &h.reply;
+            let params = h.args.iter().enumerate().map(|(i, ty)| {
+                let name = format_ident!("arg{}", i);
+                quote!(#name: #ty)
+            });
+            quote! {
+                fn #method(&self, #(#params),*) -> impl ::std::future::Future<
+                    Output = ::std::result::Result<#reply, ::miniactor::AskError>,
+                > + ::std::marker::Send
+            }
+        })
+        .collect();
+
+    let methods = handlers.iter().zip(&signatures).map(|(h, signature)| {
+        let variant = &h.variant;
+        let names = (0..h.args.len()).map(|i| format_ident!("arg{}", i));
+        quote! {
+            #signature {
+                self.ask(move |reply| #msg::#variant(#(#names,)* ::std::option::Option::Some(reply)))
+            }
+        }
+    });
+
+    let msg_doc = format!("Messages handled by [`{}`].", actor);
+    let ext_doc = format!(
+        "Typed requests to [`{}`], each sends a [`{}`] and waits for the reply.",
+        actor, msg
+    );
+    let signature_docs = handlers
+        .iter()
+        .map(|h| format!("Call `{}::{}` and wait for its result.", actor, h.method));
+
+    Ok(quote! {
+        #item
+
+        #[doc = #msg_doc]
+        #vis enum #msg {
+            #(#variants,)*
+        }
+
+        impl ::miniactor::Actor for #actor {
+            type Msg = #msg;
+            type Error = #error;
+
+            async fn recv(
+                &mut self,
+                msg: Self::Msg,
+                ctx: &mut ::miniactor::Context<Self>,
+            ) -> ::std::result::Result<(), Self::Error> {
+                let _ = &ctx;
+                match msg {
+                    #(#arms)*
+                }
+                ::std::result::Result::Ok(())
+            }
+        }
+
+        #[doc = #ext_doc]
+        #vis trait #ext {
+            #(#[doc = #signature_docs] #signatures;)*
+        }
+
+        impl #ext for ::miniactor::Handle<#msg> {
+            #(#methods)*
+        }
+    })
+}
+
+/// Methods callable on a `Handle` that an extension method of the same name would not override.
+const HANDLE_METHODS: &[&str] = &[
+    "id",
+    "path",
+    "send",
+    "try_send",
+    "is_closed",
+    "mailbox_len",
+    "set_error_policy",
+    "stop",
+    "downgrade",
+    "stopped",
+    "ask",
+    "recipient",
+    "monitor",
+    "demonitor",
+    "link",
+    "unlink",
+    "clone",
+    "clone_from",
+    "to_owned",
+    "eq",
+    "ne",
+    "into",
+    "try_into",
+];
+
+fn handler(method: &syn::ImplItemFn, fallible: bool) -> syn::Result<Handler> {
+    let name = &method.sig.ident;
+    let unraw = name.unraw();
+    if HANDLE_METHODS.iter().any(|reserved| unraw == reserved) {
+        return Err(syn::Error::new_spanned(
+            name,
+            format!(
+                "`{}` is a method of `Handle` and would be called instead of the handler, rename it",
+                name
+            ),
+        ));
+    }
+    let mut args = Vec::new();
+    let mut ctx = None;
+    for (pos, input) in method.sig.inputs.iter().skip(1).enumerate() {
+        let FnArg::Typed(input) = input else {
+            continue;
+        };
+        if is_context(&input.ty) {
+            ctx = Some(pos);
+        } else {
+            args.push((*input.ty).clone());
+        }
+    }
+    let reply = match &method.sig.output {
+        ReturnType::Default if fallible => {
+            return Err(syn::Error::new_spanned(
+                &method.sig,
+                "handlers of an actor with an `error` must return a Result",
+            ))
+        }
+        ReturnType::Default => syn::parse_quote!(()),
+        ReturnType::Type(_, ty) if fallible => ok_type(ty).ok_or_else(|| {
+            syn::Error::new_spanned(
+                ty,
+                "handlers of an actor with an `error` must return a Result",
+            )
+        })?,
+        ReturnType::Type(_, ty) => (**ty).clone(),
+    };
+    let variant = syn::parse_str::<Ident>(&camel_case(&unraw.to_string()))
+        .map(|variant| Ident::new(&variant.to_string(), name.span()))
+        .map_err(|_| {
+            syn::Error::new_spanned(
+                name,
+                format!("`{}` cannot be turned into a message variant name", unraw),
+            )
+        })?;
+    Ok(Handler {
+        method: name.clone(),
+        variant,
+        args,
+        ctx,
+        reply,
+    })
+}
+
+/// Returns true for `&mut Context<..>`.
+fn is_context(ty: &Type) -> bool {
+    match ty {
+        Type::Reference(reference) if reference.mutability.is_some() => match &*reference.elem {
+            Type::Path(path) => path
+                .path
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "Context"),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Returns `T` of a `Result<T, E>`.
+fn ok_type(ty: &Type) -> Option<Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Result" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        GenericArgument::Type(ty) => Some(ty.clone()),
+        _ => None,
+    }
+}
+
+fn camel_case(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            let first = chars.next().unwrap().to_ascii_uppercase();
+            first.to_string() + chars.as_str()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_camel_case() {
+        assert_eq!(camel_case("get"), "Get");
+        assert_eq!(camel_case("add_all"), "AddAll");
+        assert_eq!(camel_case("_private__name"), "PrivateName");
+    }
+
+    #[test]
+    fn test_handler_types() {
+        let ty: Type = syn::parse_quote!(&mut miniactor::Context<Self>);
+        assert!(is_context(&ty));
+        let ty: Type = syn::parse_quote!(&Context<Self>);
+        assert!(!is_context(&ty));
+
+        let ty: Type = syn::parse_quote!(Result<Vec<u32>, String>);
+        let ok = ok_type(&ty).unwrap();
+        assert_eq!(quote!(#ok).to_string(), quote!(Vec<u32>).to_string());
+        let ty: Type = syn::parse_quote!(Option<u32>);
+        assert!(ok_type(&ty).is_none());
+    }
+
+    #[test]
+    fn test_handle_method_names() {
+        let method: syn::ImplItemFn = syn::parse_quote! {
+            async fn stop(&mut self) {}
+        };
+        assert!(handler(&method, false).is_err());
+        let method: syn::ImplItemFn = syn::parse_quote! {
+            async fn shutdown(&mut self) {}
+        };
+        assert!(handler(&method, false).is_ok());
+    }
+
+    #[test]
+    fn test_variant_names() {
+        let method: syn::ImplItemFn = syn::parse_quote! {
+            async fn r#type(&mut self) {}
+        };
+        assert_eq!(handler(&method, false).unwrap().variant, "Type");
+        let method: syn::ImplItemFn = syn::parse_quote! {
+            async fn __(&mut self) {}
+        };
+        assert!(handler(&method, false).is_err());
+        let method: syn::ImplItemFn = syn::parse_quote! {
+            async fn self_(&mut self) {}
+        };
+        assert!(handler(&method, false).is_err());
+    }
+}
@@ -0,0 +1,67 @@
+use miniactor::{actor, AskError, Builder, Context, ErrorPolicy, Handle};
+
+struct Store {
+    items: Vec<String>,
+}
+
+#[actor(vis = pub(crate))]
+impl Store {
+    async fn push(&mut self, item: String) -> usize {
+        self.items.push(item);
+        self.items.len()
+    }
+
+    async fn r#type(&mut self) -> &'static str {
+        "store"
+    }
+
+    async fn take(&mut self, ctx: &mut Context<Self>, n: usize) -> Vec<String> {
+        if n >= self.items.len() {
+            ctx.stop();
+        }
+        let n = n.min(self.items.len());
+        self.items.drain(..n).collect()
+    }
+
+    fn helper(&self) -> usize {
+        self.items.len()
+    }
+}
+
+#[tokio::test]
+async fn test_generated_actor() {
+    let h = Handle::new(Store { items: Vec::new() });
+    assert_eq!(h.push("a".to_string()).await, Ok(1));
+    h.send(StoreMsg::Push("b".to_string(), None)).await.unwrap();
+    assert_eq!(h.r#type().await, Ok("store"));
+    assert_eq!(h.take(1).await, Ok(vec!["a".to_string()]));
+    assert_eq!(h.take(5).await, Ok(vec!["b".to_string()]));
+    h.stopped().await;
+    assert_eq!(h.push("c".to_string()).await, Err(AskError::Closed));
+    assert_eq!(Store { items: Vec::new() }.helper(), 0);
+}
+
+struct Parser;
+
+#[actor(msg = ParseRequest, error = std::num::ParseIntError)]
+impl Parser {
+    async fn parse(&mut self, text: String) -> Result<u32, std::num::ParseIntError> {
+        text.parse()
+    }
+}
+
+#[tokio::test]
+async fn test_generated_errors() {
+    let h = Builder::new(Parser)
+        .error_policy(ErrorPolicy::Ignore)
+        .spawn();
+    assert_eq!(h.parse("12".to_string()).await, Ok(12));
+    assert_eq!(h.parse("twelve".to_string()).await, Err(AskError::Dropped));
+    h.send(ParseRequest::Parse("1".to_string(), None))
+        .await
+        .unwrap();
+
+    let (h, join) = Builder::new(Parser).spawn_with_join();
+    assert_eq!(h.parse("x".to_string()).await, Err(AskError::Dropped));
+    assert!(join.await.is_err());
+}
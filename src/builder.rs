@@ -0,0 +1,213 @@
+//! Configuration for spawning an [`Actor`].
+
+use std::any::type_name;
+use std::sync::Arc;
+
+use crate::dead_letters::CopyFn;
+use crate::mailbox::{Config, Mailbox};
+use crate::runner::{run_actor, ActorCell, Exit, Inbox};
+use crate::{registry, Actor, ActorSystem, Context};
+use crate::{ErrorPolicy, Handle, JoinError, JoinHandle, Priority, RegistryError};
+
+/// Builder configures how an [`Actor`] is spawned.
+/// [`Handle::new`] and [`Handle::bounded`] are shorthands for the common cases.
+pub struct Builder<T: Actor> {
+    actor: T,
+    mailbox: Config<T::Msg>,
+    error_policy: ErrorPolicy,
+    system: Option<ActorSystem>,
+    parent: Option<Arc<ActorCell>>,
+    name: Option<String>,
+    copy_failed_sends: Option<CopyFn<T::Msg>>,
+}
+
+impl<T> Builder<T>
+where
+    T: Actor + 'static,
+    T::Msg: Send,
+{
+    /// Start configuring the given [`Actor`]. By default the mailbox is unbounded.
+    pub fn new(actor: T) -> Self {
+        Builder {
+            actor,
+            mailbox: Config::Unbounded,
+            error_policy: ErrorPolicy::Escalate,
+            system: None,
+            parent: None,
+            name: None,
+            copy_failed_sends: None,
+        }
+    }
+
+    /// Limit the mailbox to at most `capacity` queued messages.
+    /// Senders wait in [`Handle::send`] while the mailbox is full.
+    /// This replaces any mailbox chosen before.
+    ///
+    /// # Panics
+    ///
+    /// Spawning panics if `capacity` is 0.
+    pub fn bounded(mut self, capacity: usize) -> Self {
+        self.mailbox = Config::Bounded(capacity);
+        self
+    }
+
+    /// Give the actor a priority mailbox.
+    /// `classify` assigns every message a [`Priority`] and queued messages of a higher priority
+    /// are received before any of a lower one, so control messages skip the queue.
+    /// A priority mailbox is unbounded. This replaces any mailbox chosen before.
+    pub fn priority<F>(mut self, classify: F) -> Self
+    where
+        F: Fn(&T::Msg) -> Priority + Send + Sync + 'static,
+    {
+        self.mailbox = Config::Priority(Arc::new(classify));
+        self
+    }
+
+    /// Queue messages in a custom [`Mailbox`], see the [`mailbox`](crate::mailbox) module.
+    /// This replaces any mailbox chosen before.
+    pub fn mailbox(mut self, mailbox: impl Mailbox<T::Msg> + 'static) -> Self {
+        self.mailbox = Config::Custom(Box::new(mailbox));
+        self
+    }
+
+    /// Set what the actor does when [`Actor::recv`] returns an error.
+    /// Defaults to [`ErrorPolicy::Escalate`].
+    pub fn error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.error_policy = policy;
+        self
+    }
+
+    /// Hand a copy of every message that [`Handle::send`], [`Handle::try_send`] or
+    /// [`Handle::ask`] fails to deliver because the actor has stopped to the
+    /// [dead letters](crate::dead_letters). The send still fails and hands the message back.
+    ///
+    /// Messages sent through a [`Recipient`](crate::Recipient) or
+    /// [`Handle::tell`](crate::Handle::tell) are not copied: they are only converted into the
+    /// actor's message type once delivered, so a failed send hands back the original message
+    /// and there is no actor message to copy.
+    pub fn dead_letter_on_failed_send(mut self) -> Self
+    where
+        T::Msg: Clone,
+    {
+        self.copy_failed_sends = Some(T::Msg::clone);
+        self
+    }
+
+    /// Spawn the actor in `system` so it is stopped by [`ActorSystem::shutdown`].
+    /// Actors it spawns through its [`Context`](crate::Context) join the system as well.
+    pub fn system(mut self, system: &ActorSystem) -> Self {
+        self.system = Some(system.clone());
+        self
+    }
+
+    /// Spawn the actor as a child of the actor running with `ctx`.
+    /// The child is stopped when its parent stops or is restarted, its path is nested under the
+    /// parent's path and it joins the parent's [`ActorSystem`].
+    /// The parent waits up to 5 seconds for its children to stop and aborts the rest.
+    /// [`Context::spawn`] is a shorthand for children with the default options.
+    pub fn child_of<A: Actor>(mut self, ctx: &Context<A>) -> Self {
+        let parent = ctx.inbox.signals.cell();
+        if self.system.is_none() {
+            self.system = parent.system();
+        }
+        self.parent = Some(parent.clone());
+        self
+    }
+
+    /// Name the actor, the last segment of its path.
+    /// Defaults to the actor's [`ActorId`](crate::ActorId), such as `actor-3`.
+    /// The name must be non-empty, must not contain a `/` and must not have the form
+    /// `actor-<n>` of the names of unnamed actors, spawning fails otherwise.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Spawn the [`Actor`] and return a [`Handle`] for it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the actor's name is invalid or a running actor already has the same path,
+    /// see [`Builder::try_spawn`].
+    pub fn spawn(self) -> Handle<T::Msg> {
+        self.spawn_with_join().0
+    }
+
+    /// Spawn the [`Actor`] and return a [`Handle`] for it together with a [`JoinHandle`]
+    /// resolving to the final actor state once it has stopped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the actor's name is invalid or a running actor already has the same path,
+    /// see [`Builder::try_spawn_with_join`].
+    pub fn spawn_with_join(self) -> (Handle<T::Msg>, JoinHandle<T>) {
+        self.try_spawn_with_join()
+            .unwrap_or_else(|err| panic!("cannot spawn {}: {}", type_name::<T>(), err))
+    }
+
+    /// Spawn the [`Actor`] and return a [`Handle`] for it.
+    /// Fails with [`RegistryError::InvalidName`] if the name given to [`Builder::name`] is
+    /// invalid, and with [`RegistryError::AlreadyRegistered`] if a running actor already has the same
+    /// path, see [`registry::resolve`](crate::registry::resolve). Only named actors can clash,
+    /// the paths of unnamed actors are made of their unique [`ActorId`](crate::ActorId).
+    pub fn try_spawn(self) -> Result<Handle<T::Msg>, RegistryError> {
+        Ok(self.try_spawn_with_join()?.0)
+    }
+
+    /// Like [`Builder::spawn_with_join`], failing instead of panicking if the actor's name is
+    /// invalid or a running actor already has the same path, see [`Builder::try_spawn`].
+    pub fn try_spawn_with_join(self) -> Result<(Handle<T::Msg>, JoinHandle<T>), RegistryError> {
+        if let Some(name) = &self.name {
+            if !registry::is_valid_name(name) {
+                return Err(RegistryError::InvalidName);
+            }
+        }
+        let (cell, signals) = ActorCell::new();
+        cell.set_path(self.path(&cell));
+        let (mut sender, receiver) = self.mailbox.channel(cell.id());
+        if let Some(copy) = self.copy_failed_sends {
+            sender.copy_failed_sends(copy);
+        }
+        cell.set_error_policy(self.error_policy);
+        let handle = Handle {
+            sender: sender.clone(),
+            cell: cell.clone(),
+        };
+        registry::bind_path(&handle)?;
+        if let Some(parent) = &self.parent {
+            parent.adopt(&cell);
+        }
+        let inbox = Inbox {
+            mailbox: receiver,
+            signals,
+            weak: sender.downgrade(),
+        };
+        let mut actor = self.actor;
+        let task = tokio::spawn(async move {
+            let (inbox, exit) = run_actor(inbox, &mut actor).await;
+            drop(inbox);
+            match exit {
+                Exit::Panicked(_) | Exit::Failed(_) | Exit::Linked(_) => Err(JoinError),
+                Exit::Normal | Exit::Restarted => Ok(actor),
+            }
+        });
+        cell.set_task(task.abort_handle());
+        if let Some(system) = self.system {
+            system.register(&cell, task.abort_handle(), type_name::<T>());
+        }
+        Ok((handle, JoinHandle(task)))
+    }
+
+    /// The path of the actor: its parent's path, or the system's root, followed by its name.
+    fn path(&self, cell: &ActorCell) -> String {
+        let parent = match (&self.parent, &self.system) {
+            (Some(parent), _) => parent.path().to_string(),
+            (None, Some(system)) => system.root(),
+            (None, None) => String::new(),
+        };
+        match &self.name {
+            Some(name) => format!("{}/{}", parent, name),
+            None => format!("{}/{}", parent, cell.id()),
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Context handed to an [`Actor`] while it runs.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::task::AbortHandle;
+use tokio::time::{self, Instant, MissedTickBehavior};
+
+use crate::dead_letters::{self, Reason};
+use crate::runner::{Inbox, Signal};
+use crate::{Actor, ActorId, Builder, Handle, SendError, WeakHandle};
+
+/// Context gives a running [`Actor`] access to its own facilities.
+/// It is passed to [`Actor::recv`] and the lifecycle hooks and exposes the actor's own
+/// [`Handle`], lets it stop itself, spawn other actors and inspect its mailbox.
+///
+/// Timers scheduled through the context deliver messages to the actor's own mailbox.
+/// They do not keep the actor alive and are cancelled when the actor stops or is restarted.
+pub struct Context<A: Actor> {
+    pub(crate) inbox: Inbox<A::Msg>,
+    timers: Timers,
+}
+
+/// Timers scheduled by the actor, aborted when dropped.
+#[derive(Default)]
+struct Timers {
+    active: HashMap<TimerToken, AbortHandle>,
+    next: u64,
+}
+
+/// TimerToken identifies a timer scheduled on a [`Context`] so it can be cancelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimerToken(u64);
+
+impl<A: Actor> Context<A> {
+    pub(crate) fn new(inbox: Inbox<A::Msg>) -> Self {
+        Context {
+            inbox,
+            timers: Timers::default(),
+        }
+    }
+
+    /// Give the inbox back, cancelling every timer.
+    pub(crate) fn into_inbox(self) -> Inbox<A::Msg> {
+        self.inbox
+    }
+
+    /// Returns a [`Handle`] to this actor.
+    /// Returns `None` once every other handle has been dropped and the actor is shutting down.
+    pub fn handle(&self) -> Option<Handle<A::Msg>> {
+        self.weak_handle().upgrade()
+    }
+
+    /// Returns a [`WeakHandle`] to this actor which does not keep it alive.
+    pub fn weak_handle(&self) -> WeakHandle<A::Msg> {
+        WeakHandle {
+            sender: self.inbox.weak.clone(),
+            cell: self.inbox.signals.cell().clone(),
+        }
+    }
+
+    /// Stop this actor once the current message has been handled, see [`Handle::stop`].
+    pub fn stop(&self) {
+        self.inbox.signals.cell().signal(Signal::Stop);
+    }
+
+    /// Spawn a child actor and return its [`Handle`].
+    /// The child is stopped when this actor stops, see [`Builder::child_of`] for the details
+    /// and other options.
+    pub fn spawn<B: Actor>(&self, actor: B) -> Handle<B::Msg> {
+        Builder::new(actor).child_of(self).spawn()
+    }
+
+    /// The [`ActorId`] of this actor.
+    pub fn id(&self) -> ActorId {
+        self.inbox.signals.cell().id()
+    }
+
+    /// The path of this actor, see [`Handle::path`].
+    pub fn path(&self) -> &str {
+        self.inbox.signals.cell().path()
+    }
+
+    /// The number of messages waiting in this actor's mailbox.
+    pub fn mailbox_len(&self) -> usize {
+        self.inbox.mailbox.len()
+    }
+
+    /// Deliver `msg` to this actor once `delay` has passed.
+    /// If the actor has stopped by then the message becomes a
+    /// [dead letter](crate::dead_letters).
+    pub fn send_after(&mut self, delay: Duration, msg: A::Msg) -> TimerToken {
+        let weak = self.inbox.weak.clone();
+        let actor = self.inbox.signals.cell().id();
+        self.schedule(async move {
+            time::sleep(delay).await;
+            match weak.upgrade() {
+                Some(sender) => {
+                    if let Err(SendError(msg)) = sender.send(msg).await {
+                        dead_letters::publish(actor, Reason::Stopped, msg);
+                    }
+                }
+                None => dead_letters::publish(actor, Reason::Stopped, msg),
+            }
+        })
+    }
+
+    /// Deliver a message built by `make_msg` to this actor every `period`,
+    /// starting one period from now.
+    /// Once the actor has stopped the timer ends and its last message becomes a
+    /// [dead letter](crate::dead_letters).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    pub fn send_interval<F>(&mut self, period: Duration, mut make_msg: F) -> TimerToken
+    where
+        F: FnMut() -> A::Msg + Send + 'static,
+    {
+        let weak = self.inbox.weak.clone();
+        let actor = self.inbox.signals.cell().id();
+        let mut interval = time::interval_at(Instant::now() + period, period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        self.schedule(async move {
+            loop {
+                interval.tick().await;
+                let Some(sender) = weak.upgrade() else {
+                    dead_letters::publish(actor, Reason::Stopped, make_msg());
+                    return;
+                };
+                if let Err(SendError(msg)) = sender.send(make_msg()).await {
+                    dead_letters::publish(actor, Reason::Stopped, msg);
+                    return;
+                }
+            }
+        })
+    }
+
+    /// Cancel a timer, returns false if it had already fired or was cancelled.
+    pub fn cancel(&mut self, token: TimerToken) -> bool {
+        match self.timers.active.remove(&token) {
+            Some(timer) if !timer.is_finished() => {
+                timer.abort();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn schedule(&mut self, timer: impl Future<Output = ()> + Send + 'static) -> TimerToken {
+        let timers = &mut self.timers;
+        timers.active.retain(|_, timer| !timer.is_finished());
+        let token = TimerToken(timers.next);
+        timers.next += 1;
+        timers
+            .active
+            .insert(token, tokio::spawn(timer).abort_handle());
+        token
+    }
+}
+
+impl Drop for Timers {
+    fn drop(&mut self) {
+        for timer in self.active.values() {
+            timer.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Reply;
+    use std::convert::Infallible;
+
+    pub enum Message {
+        Tick,
+        Ping,
+        Schedule(Duration),
+        CancelTicks,
+        Get(Reply<(u32, u32)>),
+    }
+
+    #[derive(Default)]
+    pub struct Ticker {
+        ticks: u32,
+        pings: u32,
+        interval: Option<TimerToken>,
+    }
+
+    impl Actor for Ticker {
+        type Msg = Message;
+        type Error = Infallible;
+        async fn recv(
+            &mut self,
+            msg: Self::Msg,
+            ctx: &mut Context<Self>,
+        ) -> Result<(), Self::Error> {
+            match msg {
+                Message::Tick => self.ticks += 1,
+                Message::Ping => self.pings += 1,
+                Message::Schedule(delay) => {
+                    ctx.send_after(delay, Message::Ping);
+                }
+                Message::CancelTicks => {
+                    assert!(ctx.cancel(self.interval.take().unwrap()));
+                }
+                Message::Get(reply) => reply.send((self.ticks, self.pings)),
+            }
+            Ok(())
+        }
+        async fn started(&mut self, ctx: &mut Context<Self>) {
+            self.interval = Some(ctx.send_interval(Duration::from_secs(1), || Message::Tick));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_timers() {
+        let h = Handle::new(Ticker::default());
+        h.send(Message::Schedule(Duration::from_millis(1500)))
+            .await
+            .unwrap();
+        time::sleep(Duration::from_millis(3500)).await;
+        assert_eq!(h.ask(Message::Get).await, Ok((3, 1)));
+
+        h.send(Message::CancelTicks).await.unwrap();
+        time::sleep(Duration::from_secs(3)).await;
+        assert_eq!(h.ask(Message::Get).await, Ok((3, 1)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_timers_do_not_keep_actor_alive() {
+        let (h, join) = Builder::new(Ticker::default()).spawn_with_join();
+        time::sleep(Duration::from_millis(1500)).await;
+        drop(h);
+        let actor = join.await.unwrap();
+        assert_eq!(actor.ticks, 1);
+    }
+
+    pub enum Control {
+        Queued(Reply<usize>),
+        Sleep(Duration),
+        Me(Reply<bool>),
+        Spawn(Reply<Handle<Message>>),
+        Stop,
+    }
+
+    pub struct Parent;
+
+    impl Actor for Parent {
+        type Msg = Control;
+        type Error = Infallible;
+        async fn recv(
+            &mut self,
+            msg: Self::Msg,
+            ctx: &mut Context<Self>,
+        ) -> Result<(), Self::Error> {
+            match msg {
+                Control::Queued(reply) => reply.send(ctx.mailbox_len()),
+                Control::Sleep(duration) => time::sleep(duration).await,
+                Control::Me(reply) => reply.send(ctx.handle().is_some()),
+                Control::Spawn(reply) => reply.send(ctx.spawn(Ticker::default())),
+                Control::Stop => ctx.stop(),
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_context_facilities() {
+        let (h, join) = Builder::new(Parent).spawn_with_join();
+        assert_eq!(h.ask(Control::Me).await, Ok(true));
+
+        let child = h.ask(Control::Spawn).await.unwrap();
+        assert_eq!(child.ask(Message::Get).await, Ok((0, 0)));
+        assert_eq!(child.path(), format!("{}/{}", h.path(), child.id()));
+
+        // All three requests are queued while the actor sleeps.
+        h.send(Control::Sleep(Duration::from_secs(1)))
+            .await
+            .unwrap();
+        let queued = tokio::join!(
+            h.ask(Control::Queued),
+            h.ask(Control::Queued),
+            h.ask(Control::Queued)
+        );
+        assert_eq!(queued, (Ok(2), Ok(1), Ok(0)));
+
+        h.send(Control::Stop).await.unwrap();
+        join.await.unwrap();
+        assert!(h.is_closed());
+        // Children are stopped before their parent finishes.
+        assert!(child.is_closed());
+    }
+}
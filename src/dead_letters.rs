@@ -0,0 +1,398 @@
+//! Dead letters: messages that could not be delivered to their actor.
+//!
+//! Once a message is accepted by [`Handle::send`](crate::Handle::send) the caller no longer
+//! owns it, so a message lost afterwards would otherwise vanish silently. The runtime hands
+//! every such message to the dead-letter sink instead, together with the [`ActorId`] it was
+//! addressed to and the [`Reason`] it was lost. This covers
+//!
+//! - messages still queued when an actor stops without handling them, for example after a
+//!   panic or an escalated error,
+//! - messages discarded by the actor's [`Mailbox`](crate::mailbox::Mailbox),
+//! - timer messages and converted messages that arrive after the actor stopped.
+//!
+//! A failed send hands the message back in its error instead, it can be passed on with
+//! [`publish`]. Actors spawned with [`Builder::dead_letter_on_failed_send`] also hand a copy
+//! of the messages their [`Handle`](crate::Handle) fails to send to the sink.
+//!
+//! The sink is a [`Recipient`] set with [`set_sink`], usually an actor that logs, counts or
+//! stores the letters for a later replay. Dead letters are dropped while no sink is set or
+//! when the sink cannot take them without waiting.
+//!
+//! ```rust
+//! use miniactor::dead_letters::{self, DeadLetter};
+//! use miniactor::{Actor, Context, Handle};
+//! use std::convert::Infallible;
+//!
+//! pub struct Alert;
+//!
+//! impl Actor for Alert {
+//!     type Msg = DeadLetter;
+//!     type Error = Infallible;
+//!
+//!     async fn recv(&mut self, letter: Self::Msg, _ctx: &mut Context<Self>) -> Result<(), Self::Error> {
+//!         println!("lost a {} for {}: {}", letter.msg_type(), letter.actor(), letter.reason());
+//!         Ok(())
+//!     }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     dead_letters::set_sink(Handle::new(Alert));
+//! }
+//! ```
+//!
+//! [`Builder::dead_letter_on_failed_send`]: crate::Builder::dead_letter_on_failed_send
+
+use std::any::{type_name, Any};
+use std::cell::Cell;
+use std::fmt;
+use std::sync::Mutex;
+
+use crate::{ActorId, Recipient};
+
+static SINK: Mutex<Option<Recipient<DeadLetter>>> = Mutex::new(None);
+
+thread_local! {
+    /// Set while a dead letter is being handed to the sink.
+    static PUBLISHING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// DeadLetter is a message that could not be delivered to its actor.
+pub struct DeadLetter {
+    actor: ActorId,
+    reason: Reason,
+    msg_type: &'static str,
+    msg: Box<dyn Any + Send>,
+}
+
+impl DeadLetter {
+    /// The actor the message was addressed to.
+    pub fn actor(&self) -> ActorId {
+        self.actor
+    }
+
+    /// Why the message was not delivered.
+    pub fn reason(&self) -> Reason {
+        self.reason
+    }
+
+    /// The type name of the message.
+    pub fn msg_type(&self) -> &'static str {
+        self.msg_type
+    }
+
+    /// Returns true if the message is of type `M`.
+    pub fn is<M: 'static>(&self) -> bool {
+        self.msg.is::<M>()
+    }
+
+    /// Take the message out, handing the letter back if it is not of type `M`.
+    pub fn downcast<M: 'static>(self) -> Result<M, DeadLetter> {
+        match self.msg.downcast() {
+            Ok(msg) => Ok(*msg),
+            Err(msg) => Err(DeadLetter { msg, ..self }),
+        }
+    }
+}
+
+impl fmt::Debug for DeadLetter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeadLetter")
+            .field("actor", &self.actor)
+            .field("reason", &self.reason)
+            .field("msg_type", &self.msg_type)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Reason a message ended up as a [`DeadLetter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Reason {
+    /// The actor had stopped before the message could be queued.
+    Stopped,
+    /// The message was queued but the actor stopped without handling it.
+    Unhandled,
+    /// The actor's mailbox discarded the message, see
+    /// [`Push::Dropped`](crate::mailbox::Push::Dropped).
+    Dropped,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::Stopped => write!(f, "actor has stopped"),
+            Reason::Unhandled => write!(f, "actor stopped before handling it"),
+            Reason::Dropped => write!(f, "discarded by the mailbox"),
+        }
+    }
+}
+
+/// Send every dead letter to `sink`, replacing the sink set before.
+/// Accepts a [`Handle`](crate::Handle) whose message type can be built from a [`DeadLetter`],
+/// or a [`Recipient<DeadLetter>`].
+pub fn set_sink(sink: impl Into<Recipient<DeadLetter>>) {
+    *SINK.lock().unwrap() = Some(sink.into());
+}
+
+/// Remove the sink, dead letters are dropped from now on.
+pub fn clear_sink() {
+    SINK.lock().unwrap().take();
+}
+
+/// Hand `msg` to the sink as a dead letter addressed to `actor`.
+/// Useful to pass on the message of a failed send:
+///
+/// ```rust
+/// # use miniactor::{Actor, Context, Handle, SendError};
+/// # use std::convert::Infallible;
+/// use miniactor::dead_letters::{self, Reason};
+/// # pub struct Worker;
+/// # impl Actor for Worker {
+/// #     type Msg = u32;
+/// #     type Error = Infallible;
+/// #     async fn recv(&mut self, _msg: u32, _ctx: &mut Context<Self>) -> Result<(), Infallible> {
+/// #         Ok(())
+/// #     }
+/// # }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let h = Handle::new(Worker);
+/// h.stop();
+/// h.stopped().await;
+/// if let Err(SendError(msg)) = h.send(1).await {
+///     dead_letters::publish(h.id(), Reason::Stopped, msg);
+/// }
+/// # }
+/// ```
+///
+/// Messages lost while handing a dead letter to the sink, for example because the sink's own
+/// mailbox discards them, are dropped so the sink cannot feed itself.
+pub fn publish<M: Send + 'static>(actor: ActorId, reason: Reason, msg: M) {
+    if PUBLISHING.get() {
+        return;
+    }
+    // Clone the sink out so the lock is not held while sending.
+    let Some(sink) = SINK.lock().unwrap().clone() else {
+        return;
+    };
+    PUBLISHING.set(true);
+    let _ = sink.try_send(DeadLetter {
+        actor,
+        reason,
+        msg_type: type_name::<M>(),
+        msg: Box::new(msg),
+    });
+    PUBLISHING.set(false);
+}
+
+/// Forwards the lost messages of one actor to the sink.
+/// It is created where the message type is known to be `Send + 'static`, so the mailbox
+/// holding it needs no bounds on its message type.
+pub(crate) struct Forward<M> {
+    actor: ActorId,
+    publish: fn(ActorId, Reason, M),
+    /// Copies the messages of failed sends when they are forwarded as well.
+    copy: Option<CopyFn<M>>,
+}
+
+/// Copies a message, see [`Forward::copy_failed_sends`].
+pub(crate) type CopyFn<M> = fn(&M) -> M;
+
+impl<M: Send + 'static> Forward<M> {
+    pub(crate) fn new(actor: ActorId) -> Self {
+        Forward {
+            actor,
+            publish: publish::<M>,
+            copy: None,
+        }
+    }
+}
+
+impl<M> Forward<M> {
+    pub(crate) fn send(&self, reason: Reason, msg: M) {
+        (self.publish)(self.actor, reason, msg)
+    }
+
+    /// Forward a copy of the messages of failed sends from now on.
+    pub(crate) fn copy_failed_sends(&mut self, copy: CopyFn<M>) {
+        self.copy = Some(copy);
+    }
+
+    /// Forward a copy of a message that could not be sent because the actor stopped.
+    pub(crate) fn failed_send(&self, msg: &M) {
+        if let Some(copy) = self.copy {
+            self.send(Reason::Stopped, copy(msg));
+        }
+    }
+}
+
+impl<M> Clone for Forward<M> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<M> Copy for Forward<M> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mailbox::{Dropping, Overflow};
+    use crate::{Actor, Builder, Context, Handle, Reply, SendError, TrySendError};
+    use tokio::sync::oneshot;
+
+    pub enum SinkMessage {
+        Letter(DeadLetter),
+        Take(Reply<Vec<DeadLetter>>),
+    }
+
+    impl From<DeadLetter> for SinkMessage {
+        fn from(letter: DeadLetter) -> Self {
+            SinkMessage::Letter(letter)
+        }
+    }
+
+    pub struct Sink(Vec<DeadLetter>);
+
+    impl Actor for Sink {
+        type Msg = SinkMessage;
+        type Error = String;
+        async fn recv(
+            &mut self,
+            msg: Self::Msg,
+            _ctx: &mut Context<Self>,
+        ) -> Result<(), Self::Error> {
+            match msg {
+                SinkMessage::Letter(letter) => self.0.push(letter),
+                SinkMessage::Take(reply) => reply.send(std::mem::take(&mut self.0)),
+            }
+            Ok(())
+        }
+    }
+
+    pub enum Message {
+        /// Wait for the release, then fail if the flag is set.
+        Block(oneshot::Receiver<()>, bool),
+        Push(u32),
+    }
+
+    pub struct Worker;
+
+    impl Actor for Worker {
+        type Msg = Message;
+        type Error = String;
+        async fn recv(
+            &mut self,
+            msg: Self::Msg,
+            _ctx: &mut Context<Self>,
+        ) -> Result<(), Self::Error> {
+            match msg {
+                Message::Block(release, fail) => {
+                    let _ = release.await;
+                    if fail {
+                        return Err("failed".to_string());
+                    }
+                }
+                Message::Push(_) => {}
+            }
+            Ok(())
+        }
+    }
+
+    pub struct Idle;
+
+    impl Actor for Idle {
+        type Msg = u32;
+        type Error = String;
+        async fn recv(&mut self, _msg: u32, _ctx: &mut Context<Self>) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    /// Returns why the pushed value of a letter was lost.
+    fn pushed(letter: DeadLetter) -> (Reason, u32) {
+        let reason = letter.reason();
+        assert_eq!(letter.msg_type(), type_name::<Message>());
+        match letter.downcast::<Message>() {
+            Ok(Message::Push(n)) => (reason, n),
+            _ => panic!("unexpected dead letter"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dead_letters() {
+        let sink = Handle::new(Sink(Vec::new()));
+        set_sink(sink.clone());
+
+        // A full dropping mailbox discards the new message.
+        let (release, blocked) = oneshot::channel();
+        let dropping = Builder::new(Worker)
+            .mailbox(Dropping::new(1, Overflow::DropNewest))
+            .spawn();
+        dropping.send(Message::Block(blocked, false)).await.unwrap();
+        while dropping.mailbox_len() > 0 {
+            tokio::task::yield_now().await;
+        }
+        dropping.try_send(Message::Push(1)).unwrap();
+        dropping.try_send(Message::Push(2)).unwrap();
+        release.send(()).unwrap();
+
+        // A failing actor leaves its queued messages unhandled.
+        let (release, blocked) = oneshot::channel();
+        let (failing, join) = Builder::new(Worker).spawn_with_join();
+        failing.send(Message::Block(blocked, true)).await.unwrap();
+        failing.send(Message::Push(3)).await.unwrap();
+        release.send(()).unwrap();
+        assert!(join.await.is_err());
+        assert_eq!(failing.mailbox_len(), 0);
+
+        // A failed send is handed back and can be published explicitly.
+        let Err(SendError(msg)) = failing.send(Message::Push(4)).await else {
+            panic!("the actor has stopped");
+        };
+        publish(failing.id(), Reason::Stopped, msg);
+
+        // When asked for a copy of a failed send goes to the sink as well.
+        let (forwarding, join) = Builder::new(Idle)
+            .dead_letter_on_failed_send()
+            .spawn_with_join();
+        forwarding.stop();
+        join.await.unwrap();
+        assert!(matches!(forwarding.send(5).await, Err(SendError(5))));
+        assert!(matches!(
+            forwarding.try_send(6),
+            Err(TrySendError::Closed(6))
+        ));
+        // A recipient hands back its message without a copy.
+        let recipient = forwarding.recipient::<u32>();
+        assert!(matches!(
+            recipient.try_send(7),
+            Err(TrySendError::Closed(7))
+        ));
+
+        let letters = sink.ask(SinkMessage::Take).await.unwrap();
+        clear_sink();
+        let (copied, letters): (Vec<_>, Vec<_>) = letters
+            .into_iter()
+            .partition(|letter| letter.actor() == forwarding.id());
+        let copied: Vec<_> = copied
+            .into_iter()
+            .map(|letter| (letter.reason(), letter.downcast::<u32>().unwrap()))
+            .collect();
+        assert_eq!(copied, [(Reason::Stopped, 5), (Reason::Stopped, 6)]);
+        let lost: Vec<_> = letters
+            .into_iter()
+            .filter(|letter| [dropping.id(), failing.id()].contains(&letter.actor()))
+            .map(pushed)
+            .collect();
+        assert_eq!(
+            lost,
+            [
+                (Reason::Dropped, 2),
+                (Reason::Unhandled, 3),
+                (Reason::Stopped, 4)
+            ]
+        );
+    }
+}
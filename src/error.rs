@@ -0,0 +1,140 @@
+//! Error types returned when talking to an [`Actor`](crate::Actor).
+
+use std::fmt;
+
+/// Error returned by [`Handle::ask`](crate::Handle::ask) when no reply could be received.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AskError {
+    /// The [`Actor`](crate::Actor) has stopped and the request was not delivered.
+    Closed,
+    /// The [`Actor`](crate::Actor) dropped the [`Reply`](crate::Reply) without answering.
+    Dropped,
+}
+
+impl fmt::Display for AskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AskError::Closed => write!(f, "actor is not running"),
+            AskError::Dropped => write!(f, "actor dropped the reply"),
+        }
+    }
+}
+
+impl std::error::Error for AskError {}
+
+/// Error returned by a [`JoinHandle`](crate::JoinHandle) when the [`Actor`](crate::Actor)
+/// task panicked or was aborted and its final state is lost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JoinError;
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "actor did not stop cleanly")
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+/// Error returned by [`SupervisorHandle::join`](crate::SupervisorHandle::join) when the
+/// [`Supervisor`](crate::Supervisor) gave up because its children failed too often.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestartLimitExceeded;
+
+impl fmt::Display for RestartLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "supervisor exceeded its restart limit")
+    }
+}
+
+impl std::error::Error for RestartLimitExceeded {}
+
+/// Error returned when registering a [`Handle`](crate::Handle) in the
+/// [`registry`](crate::registry).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistryError {
+    /// Another running actor is registered under the name, or already has the path of an
+    /// actor being spawned.
+    AlreadyRegistered,
+    /// The [`Actor`](crate::Actor) has already stopped.
+    NotRunning,
+    /// The name of an actor being spawned is empty, contains a `/` or has the form `actor-<n>`
+    /// of the names of unnamed actors.
+    InvalidName,
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::AlreadyRegistered => write!(f, "name is already registered"),
+            RegistryError::NotRunning => write!(f, "actor is not running"),
+            RegistryError::InvalidName => write!(f, "invalid actor name"),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+/// Error returned by [`Handle::send`](crate::Handle::send) when the [`Actor`](crate::Actor)
+/// has stopped. The undelivered message is handed back.
+#[derive(PartialEq, Eq)]
+pub struct SendError<M>(pub M);
+
+impl<M> SendError<M> {
+    /// Take back the message that could not be delivered.
+    pub fn into_inner(self) -> M {
+        self.0
+    }
+}
+
+impl<M> fmt::Debug for SendError<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SendError(..)")
+    }
+}
+
+impl<M> fmt::Display for SendError<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "actor is not running")
+    }
+}
+
+impl<M> std::error::Error for SendError<M> {}
+
+/// Error returned by [`Handle::try_send`](crate::Handle::try_send).
+/// The undelivered message is handed back.
+#[derive(PartialEq, Eq)]
+pub enum TrySendError<M> {
+    /// The mailbox is bounded and currently full.
+    Full(M),
+    /// The [`Actor`](crate::Actor) has stopped.
+    Closed(M),
+}
+
+impl<M> TrySendError<M> {
+    /// Take back the message that could not be delivered.
+    pub fn into_inner(self) -> M {
+        match self {
+            TrySendError::Full(msg) | TrySendError::Closed(msg) => msg,
+        }
+    }
+}
+
+impl<M> fmt::Debug for TrySendError<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => write!(f, "Full(..)"),
+            TrySendError::Closed(_) => write!(f, "Closed(..)"),
+        }
+    }
+}
+
+impl<M> fmt::Display for TrySendError<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => write!(f, "mailbox is full"),
+            TrySendError::Closed(_) => write!(f, "actor is not running"),
+        }
+    }
+}
+
+impl<M> std::error::Error for TrySendError<M> {}
@@ -0,0 +1,264 @@
+//! Actors handling several independent message types.
+//!
+//! Instead of one message enum, an [`Actor`] can implement [`Handler<M>`] once per message
+//! type, each with its own reply. Its [`Actor::Msg`] is then an [`Envelope`] which dispatches to
+//! the right handler, and [`Handle::tell`] and [`Handle::request`] accept any handled message.
+//!
+//! ```rust
+//! use miniactor::{Actor, Context, Envelope, Handle, Handler, Message};
+//! use std::convert::Infallible;
+//!
+//! pub struct Add(u32);
+//! pub struct Get;
+//!
+//! impl Message for Add {
+//!     type Reply = ();
+//! }
+//!
+//! impl Message for Get {
+//!     type Reply = u32;
+//! }
+//!
+//! pub struct Counter(u32);
+//!
+//! impl Actor for Counter {
+//!     type Msg = Envelope<Self>;
+//!     type Error = Infallible;
+//!
+//!     async fn recv(&mut self, msg: Self::Msg, ctx: &mut Context<Self>) -> Result<(), Self::Error> {
+//!         msg.dispatch(self, ctx).await
+//!     }
+//! }
+//!
+//! impl Handler<Add> for Counter {
+//!     async fn handle(&mut self, Add(n): Add, _ctx: &mut Context<Self>) -> Result<(), Infallible> {
+//!         self.0 += n;
+//!         Ok(())
+//!     }
+//! }
+//!
+//! impl Handler<Get> for Counter {
+//!     async fn handle(&mut self, _msg: Get, _ctx: &mut Context<Self>) -> Result<u32, Infallible> {
+//!         Ok(self.0)
+//!     }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let h = Handle::new(Counter(0));
+//!     h.tell(Add(2)).await.unwrap();
+//!     assert_eq!(h.request(Get).await, Ok(2));
+//! }
+//! ```
+
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::{Actor, AskError, Context, Handle, Reply, SendError, TrySendError};
+
+type DispatchFuture<'a, A> =
+    Pin<Box<dyn Future<Output = Result<(), <A as Actor>::Error>> + Send + 'a>>;
+
+/// Message is implemented by every type an [`Actor`] can handle through a [`Handler`].
+pub trait Message: Send + 'static {
+    /// The value the handler answers with, use `()` if there is nothing to answer.
+    type Reply: Send + 'static;
+}
+
+/// Handler is implemented by an [`Actor`] once for every [`Message`] type it handles.
+pub trait Handler<M: Message>: Actor {
+    /// handle is called with every message of type `M`, the reply is sent back to the caller
+    /// of [`Handle::request`].
+    /// An error is treated like an error returned from [`Actor::recv`] and the caller receives
+    /// [`AskError::Dropped`].
+    fn handle(
+        &mut self,
+        msg: M,
+        ctx: &mut Context<Self>,
+    ) -> impl Future<Output = Result<M::Reply, Self::Error>> + Send;
+}
+
+/// Envelope carries any message an [`Actor`] has a [`Handler`] for.
+/// Use it as [`Actor::Msg`] and forward it with [`Envelope::dispatch`] in [`Actor::recv`].
+/// Every handled message converts into an envelope, so [`Handle::recipient`] gives a
+/// [`Recipient`](crate::Recipient) for a single message type.
+pub struct Envelope<A>(Box<dyn Dispatch<A>>);
+
+impl<A: Actor> Envelope<A> {
+    /// Wrap a message, the reply is discarded.
+    pub fn new<M: Message>(msg: M) -> Self
+    where
+        A: Handler<M>,
+    {
+        Envelope(Box::new(Packed { msg, reply: None }))
+    }
+
+    /// Wrap a message together with the [`Reply`] its answer is sent to.
+    pub fn with_reply<M: Message>(msg: M, reply: Reply<M::Reply>) -> Self
+    where
+        A: Handler<M>,
+    {
+        Envelope(Box::new(Packed {
+            msg,
+            reply: Some(reply),
+        }))
+    }
+
+    /// Pass the message to the matching [`Handler`] of `actor` and send back the reply.
+    pub async fn dispatch(self, actor: &mut A, ctx: &mut Context<A>) -> Result<(), A::Error> {
+        self.0.dispatch(actor, ctx).await
+    }
+}
+
+impl<A: Handler<M>, M: Message> From<M> for Envelope<A> {
+    fn from(msg: M) -> Self {
+        Envelope::new(msg)
+    }
+}
+
+/// Object safe dispatch of a single message type.
+trait Dispatch<A: Actor>: Send {
+    fn dispatch<'a>(
+        self: Box<Self>,
+        actor: &'a mut A,
+        ctx: &'a mut Context<A>,
+    ) -> DispatchFuture<'a, A>;
+}
+
+struct Packed<M: Message> {
+    msg: M,
+    reply: Option<Reply<M::Reply>>,
+}
+
+impl<A: Handler<M>, M: Message> Dispatch<A> for Packed<M> {
+    fn dispatch<'a>(
+        self: Box<Self>,
+        actor: &'a mut A,
+        ctx: &'a mut Context<A>,
+    ) -> DispatchFuture<'a, A> {
+        Box::pin(async move {
+            let Packed { msg, reply } = *self;
+            let value = actor.handle(msg, ctx).await?;
+            if let Some(reply) = reply {
+                reply.send(value);
+            }
+            Ok(())
+        })
+    }
+}
+
+impl<A: Actor> Handle<Envelope<A>> {
+    /// Send a message to one of the actor's [`Handler`]s without waiting for the reply.
+    /// Fails if the actor has stopped, handing the message back in the [`SendError`].
+    pub async fn tell<M: Message>(&self, msg: M) -> Result<(), SendError<M>>
+    where
+        A: Handler<M>,
+    {
+        self.sender.send_with(msg, Envelope::new).await
+    }
+
+    /// Like [`Handle::tell`] without waiting for room in the mailbox.
+    pub fn try_tell<M: Message>(&self, msg: M) -> Result<(), TrySendError<M>>
+    where
+        A: Handler<M>,
+    {
+        self.sender.try_send_with(msg, Envelope::new)
+    }
+
+    /// Send a message to one of the actor's [`Handler`]s and wait for its reply.
+    pub async fn request<M: Message>(&self, msg: M) -> Result<M::Reply, AskError>
+    where
+        A: Handler<M>,
+    {
+        self.ask(|reply| Envelope::with_reply(msg, reply)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Builder, ErrorPolicy, Recipient};
+
+    pub struct Push(u32);
+    pub struct Pop;
+    pub struct Fail;
+
+    impl Message for Push {
+        type Reply = usize;
+    }
+
+    impl Message for Pop {
+        type Reply = Option<u32>;
+    }
+
+    impl Message for Fail {
+        type Reply = ();
+    }
+
+    pub struct Stack(Vec<u32>);
+
+    impl Actor for Stack {
+        type Msg = Envelope<Self>;
+        type Error = String;
+        async fn recv(
+            &mut self,
+            msg: Self::Msg,
+            ctx: &mut Context<Self>,
+        ) -> Result<(), Self::Error> {
+            msg.dispatch(self, ctx).await
+        }
+    }
+
+    impl Handler<Push> for Stack {
+        async fn handle(
+            &mut self,
+            Push(n): Push,
+            _ctx: &mut Context<Self>,
+        ) -> Result<usize, String> {
+            self.0.push(n);
+            Ok(self.0.len())
+        }
+    }
+
+    impl Handler<Pop> for Stack {
+        async fn handle(
+            &mut self,
+            _msg: Pop,
+            _ctx: &mut Context<Self>,
+        ) -> Result<Option<u32>, String> {
+            Ok(self.0.pop())
+        }
+    }
+
+    impl Handler<Fail> for Stack {
+        async fn handle(&mut self, _msg: Fail, _ctx: &mut Context<Self>) -> Result<(), String> {
+            Err("fail".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handlers() {
+        let h = Handle::new(Stack(Vec::new()));
+        h.tell(Push(1)).await.unwrap();
+        h.try_tell(Push(2)).unwrap();
+        assert_eq!(h.request(Push(3)).await, Ok(3));
+        assert_eq!(h.request(Pop).await, Ok(Some(3)));
+        assert_eq!(h.request(Pop).await, Ok(Some(2)));
+
+        let pushes: Recipient<Push> = h.recipient();
+        pushes.send(Push(4)).await.unwrap();
+        assert_eq!(h.request(Pop).await, Ok(Some(4)));
+    }
+
+    #[tokio::test]
+    async fn test_handler_error() {
+        let (h, join) = Builder::new(Stack(vec![1]))
+            .error_policy(ErrorPolicy::Ignore)
+            .spawn_with_join();
+        assert_eq!(h.request(Fail).await, Err(AskError::Dropped));
+        assert_eq!(h.request(Pop).await, Ok(Some(1)));
+        h.stop();
+        join.await.unwrap();
+        assert!(matches!(h.tell(Push(4)).await, Err(SendError(Push(4)))));
+    }
+}
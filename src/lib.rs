@@ -7,7 +7,8 @@
 //!
 //! Example
 //! ```rust
-//! use miniactor::{Actor, Handle};
+//! use miniactor::{Actor, Context, Handle};
+//! use std::convert::Infallible;
 //!
 //! // Define our message
 //! pub enum Message {
@@ -20,13 +21,15 @@
 //! // Implement the Actor trait
 //! impl Actor for MyActor {
 //!     type Msg = Message;
+//!     type Error = Infallible;
 //!
 //!     // Just print the message for this example
-//!     fn recv(&mut self, msg: Self::Msg) {
+//!     async fn recv(&mut self, msg: Self::Msg, _ctx: &mut Context<Self>) -> Result<(), Self::Error> {
 //!         match msg {
 //!             Message::Hello => println!("Hello World from Actor!"),
 //!             Message::SecretMsg(s) => println!("Secret: {}", s),
 //!         }
+//!         Ok(())
 //!     }
 //! }
 //!
@@ -36,60 +39,423 @@
 //!     let h1 = Handle::new(MyActor);
 //!
 //!     // Send messages to the actor
-//!     h1.send(Message::Hello);
-//!     h1.send(Message::SecretMsg("foo"));
+//!     h1.send(Message::Hello).await.unwrap();
+//!     h1.send(Message::SecretMsg("foo")).await.unwrap();
 //! }
 //! ```
 
-use tokio::sync::mpsc;
+use std::fmt;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{self, Poll};
+
+use tokio::sync::oneshot;
+
+mod builder;
+mod context;
+pub mod dead_letters;
+mod error;
+mod handler;
+pub mod mailbox;
+mod monitor;
+pub mod pubsub;
+mod recipient;
+pub mod registry;
+pub mod router;
+mod runner;
+pub mod supervisor;
+pub mod system;
+
+pub use builder::Builder;
+pub use context::{Context, TimerToken};
+pub use error::{
+    AskError, JoinError, RegistryError, RestartLimitExceeded, SendError, TrySendError,
+};
+pub use handler::{Envelope, Handler, Message};
+use mailbox::{MailboxSender, WeakMailboxSender};
+#[cfg(feature = "macros")]
+pub use miniactor_macros::actor;
+pub use monitor::{Down, ExitReason, MonitorRef};
+pub use recipient::{Recipient, WeakRecipient};
+pub use router::{Router, Routing};
+use runner::{ActorCell, Signal};
+pub use supervisor::{Strategy, Supervisor, SupervisorHandle};
+pub use system::{ActorSystem, ShutdownReport};
 
 /// Actor trait implements the message type and receiver function
-pub trait Actor: Send {
+pub trait Actor: Sized + Send + 'static {
     /// The user defined type of message that the Actor can accept
-    type Msg;
+    type Msg: Send + 'static;
+
+    /// The error [`Actor::recv`] can fail with, use [`std::convert::Infallible`] if it cannot fail.
+    /// What the actor does after an error is decided by its [`ErrorPolicy`].
+    type Error: fmt::Display;
 
     /// recv is called on the [`Actor`] every time a message is received.
-    fn recv(&mut self, msg: Self::Msg);
+    /// The returned future is awaited before the next message is taken from the mailbox,
+    /// so an actor still processes its messages one at a time.
+    /// Implementations will usually be written as `async fn recv`.
+    /// The [`Context`] gives access to the actor's own facilities such as timers.
+    fn recv(
+        &mut self,
+        msg: Self::Msg,
+        ctx: &mut Context<Self>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+    /// started is called once when the [`Actor`] begins running, before the first message.
+    fn started(&mut self, ctx: &mut Context<Self>) -> impl Future<Output = ()> + Send {
+        let _ = ctx;
+        async {}
+    }
+
+    /// stopping is called once the mailbox is closed and no new messages are accepted.
+    /// Messages that were already queued by a [`Handle::stop`] are delivered after this hook.
+    fn stopping(&mut self, ctx: &mut Context<Self>) -> impl Future<Output = ()> + Send {
+        let _ = ctx;
+        async {}
+    }
+
+    /// stopped is called last, after the message loop has finished.
+    fn stopped(&mut self, ctx: &mut Context<Self>) -> impl Future<Output = ()> + Send {
+        let _ = ctx;
+        async {}
+    }
+
+    /// on_panic is called when handling a message panicked and decides what happens next.
+    /// By default the actor is restarted.
+    fn on_panic(&mut self, panic: &Panic) -> PanicAction {
+        let _ = panic;
+        PanicAction::Restart
+    }
+}
+
+/// Panic describes a panic caught while an [`Actor`] was handling a message.
+#[derive(Debug, Clone)]
+pub struct Panic {
+    message: String,
+    msg_type: &'static str,
+    processed: u64,
+}
+
+impl Panic {
+    /// The message the handler panicked with.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The type name of the message that was being handled.
+    pub fn msg_type(&self) -> &'static str {
+        self.msg_type
+    }
+
+    /// How many messages this actor instance handled before the offending one.
+    pub fn processed(&self) -> u64 {
+        self.processed
+    }
+}
+
+/// PanicAction is returned by [`Actor::on_panic`] to decide how the actor continues.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanicAction {
+    /// Keep the current state and carry on with the next message.
+    Continue,
+    /// End this actor instance as failed.
+    /// A [`Supervisor`] restarts it, an unsupervised actor stops.
+    Restart,
+    /// Stop gracefully as if [`Handle::stop`] had been called.
+    Stop,
+}
+
+/// ErrorPolicy decides what the runner does when [`Actor::recv`] returns an error.
+/// It is set with [`Builder::error_policy`] or [`Handle::set_error_policy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Drop the error and carry on with the next message.
+    Ignore,
+    /// Stop gracefully as if [`Handle::stop`] had been called.
+    Stop,
+    /// End the actor as failed. A [`Supervisor`] restarts it, an unsupervised actor stops.
+    /// This is the default.
+    Escalate,
+}
+
+/// Priority of a message in a priority mailbox, see [`Builder::priority`].
+/// Queued messages of a higher priority are always delivered before those of a lower one,
+/// messages of the same priority are delivered in the order they were sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Control messages such as shutdown or configuration reloads.
+    High,
+    /// Regular messages. This is the priority to use for most messages.
+    Normal,
+    /// Background work that can wait.
+    Low,
+}
+
+/// ActorId identifies a spawned [`Actor`].
+/// Every actor gets a new id when it is spawned, ids are never reused within a process and a
+/// supervised actor keeps its id across restarts.
+/// It is returned by [`Handle::id`] and also identifies the actor in [`Down`] notifications
+/// and [dead letters](dead_letters).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ActorId(u64);
+
+impl ActorId {
+    pub(crate) fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        ActorId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl fmt::Display for ActorId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "actor-{}", self.0)
+    }
 }
 
 /// Handle provides an interface for sending messages to the [`Actor`].
 /// The [`Handle`] can be cloned and passed around.
 /// The handle holds the lifetime of the [`Actor`] and when the _last_ handle is dropped the Actor will stop.
-pub struct Handle<M>(mpsc::UnboundedSender<M>);
+/// An actor can also be stopped explicitly with [`Handle::stop`].
+pub struct Handle<M> {
+    sender: MailboxSender<M>,
+    cell: Arc<ActorCell>,
+}
 
 impl<M> Handle<M> {
     /// Generates an [`Actor`] and returns a [`Handle`] for that [`Actor`].
-    pub fn new<T: Actor + 'static>(actor: T) -> Handle<M>
+    /// The mailbox of the actor is unbounded.
+    pub fn new<T>(actor: T) -> Handle<M>
+    where
+        T: Actor<Msg = M> + 'static,
+        M: Send,
+    {
+        Builder::new(actor).spawn()
+    }
+
+    /// Generates an [`Actor`] with a mailbox holding at most `capacity` messages
+    /// and returns a [`Handle`] for that [`Actor`].
+    /// Senders wait in [`Handle::send`] while the mailbox is full.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn bounded<T>(actor: T, capacity: usize) -> Handle<M>
     where
-        <T as Actor>::Msg: Send,
-        T: Actor<Msg = M>,
+        T: Actor<Msg = M> + 'static,
+        M: Send,
     {
-        let (sender, receiver) = mpsc::unbounded_channel::<T::Msg>();
-        tokio::spawn(run_actor(receiver, actor));
-        Handle(sender)
+        Builder::new(actor).bounded(capacity).spawn()
+    }
+
+    /// The [`ActorId`] of the [`Actor`].
+    pub fn id(&self) -> ActorId {
+        self.cell.id()
+    }
+
+    /// The path of the [`Actor`], such as `/system/ingest/worker-3`.
+    /// It can be resolved back to a handle with [`registry::resolve`].
+    pub fn path(&self) -> &str {
+        self.cell.path()
+    }
+
+    /// Send a message to the [`Actor`], waiting for room if the mailbox is full.
+    /// Fails if the actor has stopped, handing the message back in the [`SendError`].
+    pub async fn send(&self, msg: M) -> Result<(), SendError<M>> {
+        self.sender
+            .send(msg)
+            .await
+            .inspect_err(|SendError(msg)| self.sender.failed_send(msg))
     }
 
-    /// Send a message to the [`Actor`].
-    pub fn send(&self, msg: M) {
-        let _ = self.0.send(msg);
+    /// Send a message to the [`Actor`] without waiting.
+    /// Fails if the mailbox is full or the actor has stopped.
+    pub fn try_send(&self, msg: M) -> Result<(), TrySendError<M>> {
+        self.sender.try_send(msg).inspect_err(|err| {
+            if let TrySendError::Closed(msg) = err {
+                self.sender.failed_send(msg);
+            }
+        })
+    }
+
+    /// Returns true if the [`Actor`] has stopped and no longer accepts messages.
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+
+    /// The number of messages waiting in the [`Actor`]'s mailbox.
+    pub fn mailbox_len(&self) -> usize {
+        self.sender.len()
+    }
+
+    /// Change what the [`Actor`] does when [`Actor::recv`] returns an error.
+    /// The new policy applies from the next message on.
+    pub fn set_error_policy(&self, policy: ErrorPolicy) {
+        self.cell.set_error_policy(policy);
+    }
+
+    /// Ask the [`Actor`] to stop.
+    /// The mailbox is closed so further sends fail, messages already queued are still
+    /// processed and then the actor stops as if its last [`Handle`] had been dropped.
+    pub fn stop(&self) {
+        self.cell.signal(Signal::Stop);
+    }
+
+    /// Create a [`WeakHandle`] that does not keep the [`Actor`] alive.
+    pub fn downgrade(&self) -> WeakHandle<M> {
+        WeakHandle {
+            sender: self.sender.downgrade(),
+            cell: self.cell.clone(),
+        }
+    }
+
+    /// Wait until the [`Actor`] has stopped and its [`Actor::stopped`] hook has run.
+    pub async fn stopped(&self) {
+        self.cell.stopped().await
+    }
+
+    /// Send a request to the [`Actor`] and wait for its reply.
+    /// `make_msg` builds the message around the [`Reply`] that the actor answers with.
+    ///
+    /// ```rust
+    /// use miniactor::{Actor, Context, Handle, Reply};
+    /// use std::convert::Infallible;
+    ///
+    /// pub enum Message {
+    ///     Add(u32, u32, Reply<u32>),
+    /// }
+    ///
+    /// pub struct Adder;
+    ///
+    /// impl Actor for Adder {
+    ///     type Msg = Message;
+    ///     type Error = Infallible;
+    ///
+    ///     async fn recv(&mut self, msg: Self::Msg, _ctx: &mut Context<Self>) -> Result<(), Self::Error> {
+    ///         match msg {
+    ///             Message::Add(a, b, reply) => reply.send(a + b),
+    ///         }
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let h = Handle::new(Adder);
+    ///     let sum = h.ask(|reply| Message::Add(1, 2, reply)).await.unwrap();
+    ///     assert_eq!(sum, 3);
+    /// }
+    /// ```
+    pub async fn ask<R>(&self, make_msg: impl FnOnce(Reply<R>) -> M) -> Result<R, AskError> {
+        let (sender, receiver) = oneshot::channel();
+        self.send(make_msg(Reply(sender)))
+            .await
+            .map_err(|_| AskError::Closed)?;
+        receiver.await.map_err(|_| AskError::Dropped)
     }
 }
 
 impl<M> Clone for Handle<M> {
     fn clone(&self) -> Self {
-        Handle(self.0.clone())
+        Handle {
+            sender: self.sender.clone(),
+            cell: self.cell.clone(),
+        }
     }
 }
 
-async fn run_actor<T: Actor>(mut receiver: mpsc::UnboundedReceiver<T::Msg>, mut actor: T) {
-    while let Some(msg) = receiver.recv().await {
-        actor.recv(msg);
+/// Handles are equal if they address the same [`Actor`].
+impl<M> PartialEq for Handle<M> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id() == other.id()
+    }
+}
+
+impl<M> Eq for Handle<M> {}
+
+impl<M> Hash for Handle<M> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id().hash(state);
+    }
+}
+
+impl<M> fmt::Debug for Handle<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Handle")
+            .field("id", &self.id())
+            .field("path", &self.path())
+            .finish()
+    }
+}
+
+/// WeakHandle is a [`Handle`] that does not keep the [`Actor`] alive, created with
+/// [`Handle::downgrade`].
+/// Like [`std::sync::Weak`] it has to be upgraded before messages can be sent.
+pub struct WeakHandle<M> {
+    sender: WeakMailboxSender<M>,
+    cell: Arc<ActorCell>,
+}
+
+impl<M> WeakHandle<M> {
+    /// The [`ActorId`] of the [`Actor`].
+    pub fn id(&self) -> ActorId {
+        self.cell.id()
+    }
+
+    /// Get a [`Handle`] back, returns `None` once every [`Handle`] has been dropped.
+    pub fn upgrade(&self) -> Option<Handle<M>> {
+        Some(Handle {
+            sender: self.sender.upgrade()?,
+            cell: self.cell.clone(),
+        })
+    }
+}
+
+impl<M> Clone for WeakHandle<M> {
+    fn clone(&self) -> Self {
+        WeakHandle {
+            sender: self.sender.clone(),
+            cell: self.cell.clone(),
+        }
+    }
+}
+
+/// JoinHandle resolves to the final state of an [`Actor`] once it has stopped.
+/// It is returned by [`Builder::spawn_with_join`]; dropping it does not stop the actor.
+pub struct JoinHandle<T>(tokio::task::JoinHandle<Result<T, JoinError>>);
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0)
+            .poll(cx)
+            .map(|res| res.unwrap_or(Err(JoinError)))
+    }
+}
+
+/// Reply is the answering half of a request made with [`Handle::ask`].
+/// It is carried inside the message and consumed by the [`Actor`] to send back the result.
+pub struct Reply<R>(oneshot::Sender<R>);
+
+impl<R> Reply<R> {
+    /// Send the reply to the waiting caller.
+    /// If the caller has stopped waiting the value is dropped.
+    pub fn send(self, value: R) {
+        let _ = self.0.send(value);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashSet;
+    use std::convert::Infallible;
+    use std::sync::{Arc, Mutex};
+    use tokio::sync::oneshot;
 
     pub enum Message {
         Test,
@@ -99,10 +465,93 @@ mod tests {
 
     impl Actor for TestActor {
         type Msg = Message;
-        fn recv(&mut self, msg: Self::Msg) {
+        type Error = Infallible;
+        async fn recv(
+            &mut self,
+            msg: Self::Msg,
+            _ctx: &mut Context<Self>,
+        ) -> Result<(), Self::Error> {
             match msg {
                 Message::Test => println!("Recieved message"),
             }
+            Ok(())
+        }
+    }
+
+    pub enum OrderMessage {
+        Push(u32),
+        Done(oneshot::Sender<Vec<u32>>),
+        Take(Reply<Vec<u32>>),
+        Ignore(Reply<Vec<u32>>),
+        Block(oneshot::Sender<()>, oneshot::Receiver<()>),
+    }
+
+    pub struct OrderActor(Vec<u32>);
+
+    impl Actor for OrderActor {
+        type Msg = OrderMessage;
+        type Error = Infallible;
+        async fn recv(
+            &mut self,
+            msg: Self::Msg,
+            _ctx: &mut Context<Self>,
+        ) -> Result<(), Self::Error> {
+            match msg {
+                OrderMessage::Push(n) => {
+                    tokio::task::yield_now().await;
+                    self.0.push(n);
+                }
+                OrderMessage::Done(tx) => {
+                    let _ = tx.send(std::mem::take(&mut self.0));
+                }
+                OrderMessage::Take(reply) => reply.send(std::mem::take(&mut self.0)),
+                OrderMessage::Ignore(reply) => drop(reply),
+                OrderMessage::Block(started, release) => {
+                    let _ = started.send(());
+                    let _ = release.await;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    pub enum GateMessage {
+        Block(oneshot::Sender<()>, oneshot::Receiver<()>),
+        Noop,
+    }
+
+    pub struct GateActor;
+
+    impl Actor for GateActor {
+        type Msg = GateMessage;
+        type Error = Infallible;
+        async fn recv(
+            &mut self,
+            msg: Self::Msg,
+            _ctx: &mut Context<Self>,
+        ) -> Result<(), Self::Error> {
+            match msg {
+                GateMessage::Block(started, release) => {
+                    let _ = started.send(());
+                    let _ = release.await;
+                }
+                GateMessage::Noop => {}
+            }
+            Ok(())
+        }
+    }
+
+    pub struct PanicActor;
+
+    impl Actor for PanicActor {
+        type Msg = u32;
+        type Error = Infallible;
+        async fn recv(
+            &mut self,
+            _msg: Self::Msg,
+            _ctx: &mut Context<Self>,
+        ) -> Result<(), Self::Error> {
+            panic!("test panic")
         }
     }
 
@@ -110,7 +559,272 @@ mod tests {
     async fn test_clone() {
         let h1 = Handle::new(TestActor);
         let h2 = h1.clone();
-        h1.send(Message::Test);
-        h2.send(Message::Test);
+        h1.send(Message::Test).await.unwrap();
+        h2.send(Message::Test).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_async_recv_order() {
+        let h = Handle::new(OrderActor(Vec::new()));
+        for n in 0..10 {
+            h.send(OrderMessage::Push(n)).await.unwrap();
+        }
+        let (tx, rx) = oneshot::channel();
+        h.send(OrderMessage::Done(tx)).await.unwrap();
+        assert_eq!(rx.await.unwrap(), (0..10).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_ask() {
+        let h = Handle::new(OrderActor(Vec::new()));
+        h.send(OrderMessage::Push(1)).await.unwrap();
+        h.send(OrderMessage::Push(2)).await.unwrap();
+        assert_eq!(h.ask(OrderMessage::Take).await, Ok(vec![1, 2]));
+        assert_eq!(h.ask(OrderMessage::Ignore).await, Err(AskError::Dropped));
+    }
+
+    #[tokio::test]
+    async fn test_bounded() {
+        let h = Handle::bounded(GateActor, 1);
+        let (started_tx, started_rx) = oneshot::channel();
+        let (release_tx, release_rx) = oneshot::channel();
+        h.send(GateMessage::Block(started_tx, release_rx))
+            .await
+            .unwrap();
+        started_rx.await.unwrap();
+
+        assert!(h.try_send(GateMessage::Noop).is_ok());
+        assert!(matches!(
+            h.try_send(GateMessage::Noop),
+            Err(TrySendError::Full(GateMessage::Noop))
+        ));
+
+        release_tx.send(()).unwrap();
+        h.send(GateMessage::Noop).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_send_closed() {
+        let h = Handle::new(PanicActor);
+        assert!(!h.is_closed());
+        h.send(1).await.unwrap();
+        while !h.is_closed() {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(h.send(2).await, Err(SendError(2)));
+        assert_eq!(h.try_send(3), Err(TrySendError::Closed(3)));
+    }
+
+    pub struct HookActor(Arc<Mutex<Vec<&'static str>>>, Option<oneshot::Sender<()>>);
+
+    impl Actor for HookActor {
+        type Msg = ();
+        type Error = Infallible;
+        async fn recv(
+            &mut self,
+            _msg: Self::Msg,
+            _ctx: &mut Context<Self>,
+        ) -> Result<(), Self::Error> {
+            self.0.lock().unwrap().push("recv");
+            Ok(())
+        }
+        async fn started(&mut self, _ctx: &mut Context<Self>) {
+            self.0.lock().unwrap().push("started");
+        }
+        async fn stopping(&mut self, _ctx: &mut Context<Self>) {
+            self.0.lock().unwrap().push("stopping");
+        }
+        async fn stopped(&mut self, _ctx: &mut Context<Self>) {
+            self.0.lock().unwrap().push("stopped");
+            let _ = self.1.take().unwrap().send(());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_hooks() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let (tx, rx) = oneshot::channel();
+        let h = Handle::new(HookActor(events.clone(), Some(tx)));
+        h.send(()).await.unwrap();
+        drop(h);
+        rx.await.unwrap();
+        assert_eq!(
+            *events.lock().unwrap(),
+            ["started", "recv", "stopping", "stopped"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stop_drains_mailbox() {
+        let (h, join) = Builder::new(OrderActor(Vec::new())).spawn_with_join();
+        for n in 0..5 {
+            h.send(OrderMessage::Push(n)).await.unwrap();
+        }
+        h.stop();
+        let actor = join.await.unwrap();
+        assert_eq!(actor.0, vec![0, 1, 2, 3, 4]);
+        assert!(h.is_closed());
+        assert!(h.send(OrderMessage::Push(5)).await.is_err());
+        h.stopped().await;
+    }
+
+    pub struct RecoverActor {
+        action: PanicAction,
+        panics: Vec<Panic>,
+        handled: u32,
+    }
+
+    impl Actor for RecoverActor {
+        type Msg = u32;
+        type Error = Infallible;
+        async fn recv(
+            &mut self,
+            msg: Self::Msg,
+            _ctx: &mut Context<Self>,
+        ) -> Result<(), Self::Error> {
+            assert!(msg != 0, "zero");
+            self.handled += msg;
+            Ok(())
+        }
+        fn on_panic(&mut self, panic: &Panic) -> PanicAction {
+            self.panics.push(panic.clone());
+            self.action
+        }
+    }
+
+    fn recover_actor(action: PanicAction) -> RecoverActor {
+        RecoverActor {
+            action,
+            panics: Vec::new(),
+            handled: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_on_panic_continue() {
+        let (h, join) = Builder::new(recover_actor(PanicAction::Continue)).spawn_with_join();
+        for n in [1, 0, 2] {
+            h.send(n).await.unwrap();
+        }
+        h.stop();
+        let actor = join.await.unwrap();
+        assert_eq!(actor.handled, 3);
+        assert_eq!(actor.panics.len(), 1);
+        assert_eq!(actor.panics[0].message(), "zero");
+        assert_eq!(actor.panics[0].msg_type(), "u32");
+        assert_eq!(actor.panics[0].processed(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_on_panic_stop_and_restart() {
+        let (h, join) = Builder::new(recover_actor(PanicAction::Stop)).spawn_with_join();
+        h.send(0).await.unwrap();
+        let actor = join.await.unwrap();
+        assert_eq!(actor.panics.len(), 1);
+        assert!(h.is_closed());
+
+        let (h, join) = Builder::new(recover_actor(PanicAction::Restart)).spawn_with_join();
+        h.send(0).await.unwrap();
+        assert_eq!(join.await.err(), Some(JoinError));
+    }
+
+    pub struct FallibleActor(Vec<u32>);
+
+    impl Actor for FallibleActor {
+        type Msg = u32;
+        type Error = String;
+        async fn recv(
+            &mut self,
+            msg: Self::Msg,
+            _ctx: &mut Context<Self>,
+        ) -> Result<(), Self::Error> {
+            if msg == 0 {
+                return Err("zero".to_string());
+            }
+            self.0.push(msg);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_error_policy() {
+        let (h, join) = Builder::new(FallibleActor(Vec::new()))
+            .error_policy(ErrorPolicy::Ignore)
+            .spawn_with_join();
+        for n in [1, 0, 2] {
+            h.send(n).await.unwrap();
+        }
+        h.stop();
+        assert_eq!(join.await.unwrap().0, vec![1, 2]);
+
+        let (h, join) = Builder::new(FallibleActor(Vec::new())).spawn_with_join();
+        h.set_error_policy(ErrorPolicy::Stop);
+        h.send(3).await.unwrap();
+        h.send(0).await.unwrap();
+        assert_eq!(join.await.unwrap().0, vec![3]);
+        assert!(h.is_closed());
+
+        let (h, join) = Builder::new(FallibleActor(Vec::new())).spawn_with_join();
+        h.send(0).await.unwrap();
+        assert_eq!(join.await.err(), Some(JoinError));
+    }
+
+    #[tokio::test]
+    async fn test_weak_handle() {
+        let (h, join) = Builder::new(OrderActor(Vec::new())).spawn_with_join();
+        let weak = h.downgrade();
+        let upgraded = weak.upgrade().unwrap();
+        upgraded.send(OrderMessage::Push(1)).await.unwrap();
+        drop(upgraded);
+
+        drop(h);
+        let actor = join.await.unwrap();
+        assert_eq!(actor.0, vec![1]);
+        assert!(weak.clone().upgrade().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_handle_identity() {
+        let a = Handle::new(TestActor);
+        let b = Handle::new(TestActor);
+        assert_eq!(a, a.clone());
+        assert_eq!(a.downgrade().id(), a.id());
+        assert_ne!(a, b);
+        assert_ne!(a.id(), b.id());
+
+        // The hash only depends on the id, which never changes.
+        #[allow(clippy::mutable_key_type)]
+        let handles: HashSet<_> = [a.clone(), b.clone(), a.clone()].into_iter().collect();
+        assert_eq!(handles.len(), 2);
+        assert!(handles.contains(&b));
+        assert_eq!(
+            format!("{:?}", a),
+            format!("Handle {{ id: {:?}, path: {:?} }}", a.id(), a.path())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_priority_mailbox() {
+        let (h, join) = Builder::new(OrderActor(Vec::new()))
+            .priority(|msg| match msg {
+                OrderMessage::Push(n) if *n >= 10 => Priority::High,
+                OrderMessage::Push(n) if *n < 5 => Priority::Low,
+                _ => Priority::Normal,
+            })
+            .spawn_with_join();
+        let (started_tx, started_rx) = oneshot::channel();
+        let (release_tx, release_rx) = oneshot::channel();
+        h.send(OrderMessage::Block(started_tx, release_rx))
+            .await
+            .unwrap();
+        started_rx.await.unwrap();
+        for n in [1, 5, 10, 2, 6, 11] {
+            h.send(OrderMessage::Push(n)).await.unwrap();
+        }
+        assert_eq!(h.mailbox_len(), 6);
+        release_tx.send(()).unwrap();
+        h.stop();
+        let actor = join.await.unwrap();
+        assert_eq!(actor.0, vec![10, 11, 5, 6, 1, 2]);
     }
 }
@@ -0,0 +1,1015 @@
+//! Mailboxes queueing the messages of an [`Actor`](crate::Actor).
+//!
+//! By default an actor gets an unbounded FIFO mailbox, [`Builder::bounded`] and
+//! [`Builder::priority`] select the other built in kinds. Any other queueing policy can be
+//! plugged in with [`Builder::mailbox`] by implementing the [`Mailbox`] trait, the runtime takes
+//! care of waking the actor and of senders waiting for room. [`Dropping`] and [`Coalescing`] are
+//! provided as ready made policies.
+//!
+//! The mailbox is chosen when the actor is spawned and does not change the type of its
+//! [`Handle`](crate::Handle), so code sending to an actor does not depend on its mailbox.
+//!
+//! ```rust
+//! use miniactor::mailbox::{Dropping, Overflow};
+//! use miniactor::{Actor, Builder, Context};
+//! use std::convert::Infallible;
+//!
+//! pub struct Sensor;
+//!
+//! impl Actor for Sensor {
+//!     type Msg = f64;
+//!     type Error = Infallible;
+//!
+//!     async fn recv(&mut self, msg: Self::Msg, _ctx: &mut Context<Self>) -> Result<(), Self::Error> {
+//!         println!("reading {}", msg);
+//!         Ok(())
+//!     }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     // Keep only the 10 latest readings when the actor falls behind.
+//!     let h = Builder::new(Sensor)
+//!         .mailbox(Dropping::new(10, Overflow::DropOldest))
+//!         .spawn();
+//!     for n in 0..100 {
+//!         h.try_send(n as f64).unwrap();
+//!     }
+//! }
+//! ```
+//!
+//! [`Builder::bounded`]: crate::Builder::bounded
+//! [`Builder::priority`]: crate::Builder::priority
+//! [`Builder::mailbox`]: crate::Builder::mailbox
+
+use std::collections::VecDeque;
+use std::pin::pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use tokio::sync::{mpsc, Notify};
+
+use crate::dead_letters::{CopyFn, Forward, Reason};
+use crate::{ActorId, Priority, SendError, TrySendError};
+
+/// Mailbox is a queueing policy for the messages of an actor.
+/// The runtime keeps the mailbox behind a lock, so implementations are plain data structures.
+pub trait Mailbox<M>: Send {
+    /// Add a message to the queue.
+    fn push(&mut self, msg: M) -> Push<M>;
+
+    /// Take the next message to deliver, `None` if the queue is empty.
+    fn pop(&mut self) -> Option<M>;
+
+    /// The number of queued messages.
+    fn len(&self) -> usize;
+
+    /// Returns true if no messages are queued.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns true if [`Mailbox::push`] would hand the message back as [`Push::Full`].
+    /// A [`Recipient`](crate::Recipient) converts its messages only once the mailbox has room,
+    /// so it can wait or hand back the original message while the mailbox is full.
+    /// `push` must not return [`Push::Full`] while this returns false, a converted message it
+    /// refuses can no longer be handed back and is discarded as a
+    /// [dead letter](crate::dead_letters).
+    fn is_full(&self) -> bool;
+}
+
+/// Push is the outcome of [`Mailbox::push`].
+pub enum Push<M> {
+    /// The message was queued.
+    Queued,
+    /// The mailbox is full and the message is handed back.
+    /// [`Handle::send`](crate::Handle::send) waits until a message is taken out and tries again,
+    /// [`Handle::try_send`](crate::Handle::try_send) fails.
+    Full(M),
+    /// The message was accepted but this message was discarded to make room for it.
+    /// It can be the pushed message itself or one that was queued before.
+    /// The discarded message is handed to the [dead letters](crate::dead_letters).
+    Dropped(M),
+}
+
+/// An unbounded FIFO mailbox.
+impl<M: Send + 'static> Mailbox<M> for VecDeque<M> {
+    fn push(&mut self, msg: M) -> Push<M> {
+        self.push_back(msg);
+        Push::Queued
+    }
+
+    fn pop(&mut self) -> Option<M> {
+        self.pop_front()
+    }
+
+    fn len(&self) -> usize {
+        VecDeque::len(self)
+    }
+
+    fn is_full(&self) -> bool {
+        false
+    }
+}
+
+/// Overflow decides which message a [`Dropping`] mailbox discards when it is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Discard the oldest queued message to make room for the new one.
+    DropOldest,
+    /// Discard the new message.
+    DropNewest,
+}
+
+/// Dropping is a bounded mailbox that never makes senders wait.
+/// When it is full a message is discarded according to its [`Overflow`].
+pub struct Dropping<M> {
+    queue: VecDeque<M>,
+    capacity: usize,
+    overflow: Overflow,
+}
+
+impl<M> Dropping<M> {
+    /// Create a mailbox holding at most `capacity` messages.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn new(capacity: usize, overflow: Overflow) -> Self {
+        assert!(capacity > 0, "mailbox capacity must be at least 1");
+        Dropping {
+            queue: VecDeque::with_capacity(capacity),
+            capacity,
+            overflow,
+        }
+    }
+}
+
+impl<M: Send + 'static> Mailbox<M> for Dropping<M> {
+    fn push(&mut self, msg: M) -> Push<M> {
+        if self.queue.len() < self.capacity {
+            self.queue.push_back(msg);
+            return Push::Queued;
+        }
+        match self.overflow {
+            Overflow::DropNewest => Push::Dropped(msg),
+            Overflow::DropOldest => {
+                let oldest = self.queue.pop_front().unwrap();
+                self.queue.push_back(msg);
+                Push::Dropped(oldest)
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Option<M> {
+        self.queue.pop_front()
+    }
+
+    fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// A dropping mailbox makes room by discarding a message instead.
+    fn is_full(&self) -> bool {
+        false
+    }
+}
+
+/// Coalescing is an unbounded mailbox that keeps at most one queued message per key.
+/// A message whose key is already queued replaces the queued message in place, messages
+/// without a key are always queued.
+pub struct Coalescing<M, K> {
+    queue: VecDeque<(Option<K>, M)>,
+    key: KeyFn<M, K>,
+}
+
+type KeyFn<M, K> = Box<dyn Fn(&M) -> Option<K> + Send>;
+
+impl<M, K> Coalescing<M, K> {
+    /// Create a mailbox coalescing messages by the key `key` returns for them.
+    pub fn new(key: impl Fn(&M) -> Option<K> + Send + 'static) -> Self {
+        Coalescing {
+            queue: VecDeque::new(),
+            key: Box::new(key),
+        }
+    }
+}
+
+impl<M: Send + 'static, K: PartialEq + Send + 'static> Mailbox<M> for Coalescing<M, K> {
+    fn push(&mut self, msg: M) -> Push<M> {
+        let key = (self.key)(&msg);
+        if key.is_some() {
+            if let Some(queued) = self.queue.iter_mut().find(|(k, _)| *k == key) {
+                return Push::Dropped(std::mem::replace(&mut queued.1, msg));
+            }
+        }
+        self.queue.push_back((key, msg));
+        Push::Queued
+    }
+
+    fn pop(&mut self) -> Option<M> {
+        self.queue.pop_front().map(|(_, msg)| msg)
+    }
+
+    fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    fn is_full(&self) -> bool {
+        false
+    }
+}
+
+/// Sending half of a mailbox, held by every [`Handle`](crate::Handle).
+pub(crate) struct MailboxSender<M> {
+    tx: Tx<M>,
+    queued: Arc<AtomicUsize>,
+    dead_letters: Forward<M>,
+}
+
+enum Tx<M> {
+    Unbounded(mpsc::UnboundedSender<M>),
+    Bounded(mpsc::Sender<M>),
+    /// One unbounded lane per [`Priority`], indexed by the classifier.
+    Priority(Arc<Classifier<M>>, [mpsc::UnboundedSender<M>; 3]),
+    Custom(QueueSender<M>),
+}
+
+/// Decides the [`Priority`] lane of a message.
+pub(crate) type Classifier<M> = dyn Fn(&M) -> Priority + Send + Sync;
+
+impl<M> MailboxSender<M> {
+    /// Send a message, waiting for capacity on a bounded mailbox.
+    pub(crate) async fn send(&self, msg: M) -> Result<(), SendError<M>> {
+        match &self.tx {
+            Tx::Custom(sender) => sender.push(msg).await,
+            Tx::Unbounded(_) | Tx::Priority(..) => self.send_unbounded(msg).map_err(SendError),
+            Tx::Bounded(sender) => match sender.reserve().await {
+                Ok(permit) => {
+                    self.queued.fetch_add(1, Ordering::Relaxed);
+                    permit.send(msg);
+                    Ok(())
+                }
+                Err(_) => Err(SendError(msg)),
+            },
+        }
+    }
+
+    /// Send a message without waiting.
+    pub(crate) fn try_send(&self, msg: M) -> Result<(), TrySendError<M>> {
+        match &self.tx {
+            Tx::Custom(sender) => sender.try_push(msg),
+            Tx::Unbounded(_) | Tx::Priority(..) => {
+                self.send_unbounded(msg).map_err(TrySendError::Closed)
+            }
+            Tx::Bounded(sender) => match sender.try_reserve() {
+                Ok(permit) => {
+                    self.queued.fetch_add(1, Ordering::Relaxed);
+                    permit.send(msg);
+                    Ok(())
+                }
+                Err(mpsc::error::TrySendError::Full(())) => Err(TrySendError::Full(msg)),
+                Err(mpsc::error::TrySendError::Closed(())) => Err(TrySendError::Closed(msg)),
+            },
+        }
+    }
+
+    /// Send a message that is converted into the mailbox type by `into` once there is room
+    /// for it, so the original message can be handed back on failure.
+    pub(crate) async fn send_with<T>(
+        &self,
+        msg: T,
+        into: impl Fn(T) -> M,
+    ) -> Result<(), SendError<T>> {
+        match &self.tx {
+            Tx::Custom(sender) => sender.push_with(msg, into).await,
+            Tx::Unbounded(_) | Tx::Priority(..) => {
+                self.send_unbounded_with(msg, into).map_err(SendError)
+            }
+            Tx::Bounded(sender) => match sender.reserve().await {
+                Ok(permit) => {
+                    self.queued.fetch_add(1, Ordering::Relaxed);
+                    permit.send(into(msg));
+                    Ok(())
+                }
+                Err(_) => Err(SendError(msg)),
+            },
+        }
+    }
+
+    /// Like [`MailboxSender::send_with`] without waiting.
+    pub(crate) fn try_send_with<T>(
+        &self,
+        msg: T,
+        into: impl FnOnce(T) -> M,
+    ) -> Result<(), TrySendError<T>> {
+        match &self.tx {
+            Tx::Custom(sender) => sender.try_push_with(msg, into),
+            Tx::Unbounded(_) | Tx::Priority(..) => self
+                .send_unbounded_with(msg, into)
+                .map_err(TrySendError::Closed),
+            Tx::Bounded(sender) => match sender.try_reserve() {
+                Ok(permit) => {
+                    self.queued.fetch_add(1, Ordering::Relaxed);
+                    permit.send(into(msg));
+                    Ok(())
+                }
+                Err(mpsc::error::TrySendError::Full(())) => Err(TrySendError::Full(msg)),
+                Err(mpsc::error::TrySendError::Closed(())) => Err(TrySendError::Closed(msg)),
+            },
+        }
+    }
+
+    /// Send on an unbounded or priority mailbox, handing the message back if the actor
+    /// stopped.
+    fn send_unbounded(&self, msg: M) -> Result<(), M> {
+        let sender = match &self.tx {
+            Tx::Unbounded(sender) => sender,
+            Tx::Priority(classify, lanes) => &lanes[classify(&msg) as usize],
+            Tx::Bounded(_) | Tx::Custom(_) => unreachable!("only unbounded channels send directly"),
+        };
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        sender.send(msg).map_err(|e| {
+            self.queued.fetch_sub(1, Ordering::Relaxed);
+            e.0
+        })
+    }
+
+    /// Convert and send a message on an unbounded or priority mailbox, handing it back if the
+    /// actor stopped.
+    fn send_unbounded_with<T>(&self, msg: T, into: impl FnOnce(T) -> M) -> Result<(), T> {
+        if self.is_closed() {
+            return Err(msg);
+        }
+        // The actor can stop between the check and the send, the message then becomes a dead
+        // letter as if it had been queued just before the mailbox closed.
+        if let Err(msg) = self.send_unbounded(into(msg)) {
+            self.dead_letters.send(Reason::Stopped, msg);
+        }
+        Ok(())
+    }
+
+    /// Hand a copy of the messages of failed sends to the dead letters from now on.
+    pub(crate) fn copy_failed_sends(&mut self, copy: CopyFn<M>) {
+        self.dead_letters.copy_failed_sends(copy);
+    }
+
+    /// Called with the message of a send that failed because the actor stopped.
+    pub(crate) fn failed_send(&self, msg: &M) {
+        self.dead_letters.failed_send(msg);
+    }
+
+    /// The number of messages waiting in the mailbox.
+    pub(crate) fn len(&self) -> usize {
+        match &self.tx {
+            Tx::Custom(sender) => sender.len(),
+            _ => self.queued.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Create a sender that does not keep the mailbox open.
+    pub(crate) fn downgrade(&self) -> WeakMailboxSender<M> {
+        let tx = match &self.tx {
+            Tx::Unbounded(sender) => WeakTx::Unbounded(sender.downgrade()),
+            Tx::Bounded(sender) => WeakTx::Bounded(sender.downgrade()),
+            Tx::Priority(classify, lanes) => {
+                WeakTx::Priority(classify.clone(), lanes.each_ref().map(|l| l.downgrade()))
+            }
+            Tx::Custom(sender) => WeakTx::Custom(sender.0.clone()),
+        };
+        WeakMailboxSender {
+            tx,
+            queued: self.queued.clone(),
+            dead_letters: self.dead_letters,
+        }
+    }
+
+    /// Returns true once the receiving actor has stopped.
+    pub(crate) fn is_closed(&self) -> bool {
+        match &self.tx {
+            Tx::Unbounded(sender) => sender.is_closed(),
+            Tx::Bounded(sender) => sender.is_closed(),
+            Tx::Priority(_, lanes) => lanes[0].is_closed(),
+            Tx::Custom(sender) => sender.is_closed(),
+        }
+    }
+}
+
+impl<M> Clone for MailboxSender<M> {
+    fn clone(&self) -> Self {
+        let tx = match &self.tx {
+            Tx::Unbounded(sender) => Tx::Unbounded(sender.clone()),
+            Tx::Bounded(sender) => Tx::Bounded(sender.clone()),
+            Tx::Priority(classify, lanes) => Tx::Priority(classify.clone(), lanes.clone()),
+            Tx::Custom(sender) => Tx::Custom(sender.clone()),
+        };
+        MailboxSender {
+            tx,
+            queued: self.queued.clone(),
+            dead_letters: self.dead_letters,
+        }
+    }
+}
+
+/// Sending half of a mailbox that does not keep the actor alive.
+pub(crate) struct WeakMailboxSender<M> {
+    tx: WeakTx<M>,
+    queued: Arc<AtomicUsize>,
+    dead_letters: Forward<M>,
+}
+
+enum WeakTx<M> {
+    Unbounded(mpsc::WeakUnboundedSender<M>),
+    Bounded(mpsc::WeakSender<M>),
+    Priority(Arc<Classifier<M>>, [mpsc::WeakUnboundedSender<M>; 3]),
+    Custom(Arc<Queue<M>>),
+}
+
+impl<M> WeakMailboxSender<M> {
+    /// Get a sender back, returns `None` once every [`Handle`](crate::Handle) is gone.
+    pub(crate) fn upgrade(&self) -> Option<MailboxSender<M>> {
+        let tx = match &self.tx {
+            WeakTx::Unbounded(sender) => Tx::Unbounded(sender.upgrade()?),
+            WeakTx::Bounded(sender) => Tx::Bounded(sender.upgrade()?),
+            WeakTx::Priority(classify, [high, normal, low]) => Tx::Priority(
+                classify.clone(),
+                [high.upgrade()?, normal.upgrade()?, low.upgrade()?],
+            ),
+            WeakTx::Custom(queue) => Tx::Custom(QueueSender::upgrade(queue)?),
+        };
+        Some(MailboxSender {
+            tx,
+            queued: self.queued.clone(),
+            dead_letters: self.dead_letters,
+        })
+    }
+}
+
+impl<M> Clone for WeakMailboxSender<M> {
+    fn clone(&self) -> Self {
+        let tx = match &self.tx {
+            WeakTx::Unbounded(sender) => WeakTx::Unbounded(sender.clone()),
+            WeakTx::Bounded(sender) => WeakTx::Bounded(sender.clone()),
+            WeakTx::Priority(classify, lanes) => WeakTx::Priority(classify.clone(), lanes.clone()),
+            WeakTx::Custom(queue) => WeakTx::Custom(queue.clone()),
+        };
+        WeakMailboxSender {
+            tx,
+            queued: self.queued.clone(),
+            dead_letters: self.dead_letters,
+        }
+    }
+}
+
+/// Receiving half of a mailbox, owned by the running actor.
+pub(crate) struct MailboxReceiver<M> {
+    rx: Rx<M>,
+    queued: Arc<AtomicUsize>,
+    dead_letters: Forward<M>,
+}
+
+enum Rx<M> {
+    Unbounded(mpsc::UnboundedReceiver<M>),
+    Bounded(mpsc::Receiver<M>),
+    Priority([mpsc::UnboundedReceiver<M>; 3]),
+    Custom(QueueReceiver<M>),
+}
+
+impl<M> MailboxReceiver<M> {
+    /// Receive the next message, returns `None` once every sender is gone.
+    pub(crate) async fn recv(&mut self) -> Option<M> {
+        let msg = match &mut self.rx {
+            Rx::Custom(receiver) => return receiver.pop().await,
+            Rx::Unbounded(receiver) => receiver.recv().await,
+            Rx::Bounded(receiver) => receiver.recv().await,
+            // Higher lanes are always polled first, so they are drained before lower ones.
+            Rx::Priority([high, normal, low]) => tokio::select! {
+                biased;
+                Some(msg) = high.recv() => Some(msg),
+                Some(msg) = normal.recv() => Some(msg),
+                Some(msg) = low.recv() => Some(msg),
+                else => None,
+            },
+        };
+        if msg.is_some() {
+            self.queued.fetch_sub(1, Ordering::Relaxed);
+        }
+        msg
+    }
+
+    /// The number of messages waiting to be received.
+    pub(crate) fn len(&self) -> usize {
+        match &self.rx {
+            Rx::Unbounded(receiver) => receiver.len(),
+            Rx::Bounded(receiver) => receiver.len(),
+            Rx::Priority(lanes) => lanes.iter().map(|lane| lane.len()).sum(),
+            Rx::Custom(receiver) => receiver.0.lock().mailbox.len(),
+        }
+    }
+
+    /// Refuse new messages while keeping the ones already queued.
+    pub(crate) fn close(&mut self) {
+        match &mut self.rx {
+            Rx::Unbounded(receiver) => receiver.close(),
+            Rx::Bounded(receiver) => receiver.close(),
+            Rx::Priority(lanes) => lanes.iter_mut().for_each(|lane| lane.close()),
+            Rx::Custom(receiver) => receiver.close(),
+        }
+    }
+
+    /// Take a queued message without waiting.
+    fn try_recv(&mut self) -> Option<M> {
+        let msg = match &mut self.rx {
+            Rx::Custom(receiver) => return receiver.try_pop(),
+            Rx::Unbounded(receiver) => receiver.try_recv().ok(),
+            Rx::Bounded(receiver) => receiver.try_recv().ok(),
+            Rx::Priority(lanes) => lanes.iter_mut().find_map(|lane| lane.try_recv().ok()),
+        };
+        if msg.is_some() {
+            self.queued.fetch_sub(1, Ordering::Relaxed);
+        }
+        msg
+    }
+}
+
+/// Messages still queued when the receiver goes away were never handled, for example because
+/// the actor panicked, they are handed to the dead letters.
+impl<M> Drop for MailboxReceiver<M> {
+    fn drop(&mut self) {
+        self.close();
+        while let Some(msg) = self.try_recv() {
+            self.dead_letters.send(Reason::Unhandled, msg);
+        }
+    }
+}
+
+/// The kind of mailbox an actor is spawned with.
+pub(crate) enum Config<M> {
+    Unbounded,
+    Bounded(usize),
+    Priority(Arc<Classifier<M>>),
+    Custom(Box<dyn Mailbox<M>>),
+}
+
+impl<M: Send + 'static> Config<M> {
+    /// Create the mailbox of the actor `actor`.
+    pub(crate) fn channel(self, actor: ActorId) -> (MailboxSender<M>, MailboxReceiver<M>) {
+        let dead_letters = Forward::new(actor);
+        match self {
+            Config::Unbounded => unbounded(dead_letters),
+            Config::Bounded(capacity) => bounded(capacity, dead_letters),
+            Config::Priority(classify) => priority(classify, dead_letters),
+            Config::Custom(mailbox) => custom(mailbox, dead_letters),
+        }
+    }
+}
+
+/// Create a mailbox without a limit on queued messages.
+fn unbounded<M>(dead_letters: Forward<M>) -> (MailboxSender<M>, MailboxReceiver<M>) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let queued = Arc::new(AtomicUsize::new(0));
+    (
+        MailboxSender {
+            tx: Tx::Unbounded(sender),
+            queued: queued.clone(),
+            dead_letters,
+        },
+        MailboxReceiver {
+            rx: Rx::Unbounded(receiver),
+            queued,
+            dead_letters,
+        },
+    )
+}
+
+/// Create a mailbox holding at most `capacity` queued messages.
+fn bounded<M>(capacity: usize, dead_letters: Forward<M>) -> (MailboxSender<M>, MailboxReceiver<M>) {
+    let (sender, receiver) = mpsc::channel(capacity);
+    let queued = Arc::new(AtomicUsize::new(0));
+    (
+        MailboxSender {
+            tx: Tx::Bounded(sender),
+            queued: queued.clone(),
+            dead_letters,
+        },
+        MailboxReceiver {
+            rx: Rx::Bounded(receiver),
+            queued,
+            dead_letters,
+        },
+    )
+}
+
+/// Create an unbounded mailbox delivering messages by the [`Priority`] `classify` assigns them.
+fn priority<M>(
+    classify: Arc<Classifier<M>>,
+    dead_letters: Forward<M>,
+) -> (MailboxSender<M>, MailboxReceiver<M>) {
+    let (high, high_rx) = mpsc::unbounded_channel();
+    let (normal, normal_rx) = mpsc::unbounded_channel();
+    let (low, low_rx) = mpsc::unbounded_channel();
+    let queued = Arc::new(AtomicUsize::new(0));
+    (
+        MailboxSender {
+            tx: Tx::Priority(classify, [high, normal, low]),
+            queued: queued.clone(),
+            dead_letters,
+        },
+        MailboxReceiver {
+            rx: Rx::Priority([high_rx, normal_rx, low_rx]),
+            queued,
+            dead_letters,
+        },
+    )
+}
+
+/// Create a mailbox queueing messages in a user supplied [`Mailbox`].
+fn custom<M>(
+    mailbox: Box<dyn Mailbox<M>>,
+    dead_letters: Forward<M>,
+) -> (MailboxSender<M>, MailboxReceiver<M>) {
+    let queue = Arc::new(Queue {
+        state: Mutex::new(State {
+            mailbox,
+            senders: 1,
+            closed: false,
+        }),
+        readable: Notify::new(),
+        writable: Notify::new(),
+        dead_letters,
+    });
+    (
+        MailboxSender {
+            tx: Tx::Custom(QueueSender(queue.clone())),
+            queued: Arc::new(AtomicUsize::new(0)),
+            dead_letters,
+        },
+        MailboxReceiver {
+            rx: Rx::Custom(QueueReceiver(queue)),
+            queued: Arc::new(AtomicUsize::new(0)),
+            dead_letters,
+        },
+    )
+}
+
+/// Channel around a [`Mailbox`], shared by its senders and receiver.
+struct Queue<M> {
+    state: Mutex<State<M>>,
+    /// Wakes the receiver when a message is queued or the last sender is gone.
+    readable: Notify,
+    /// Wakes senders waiting for room when a message is taken out or the receiver closes.
+    writable: Notify,
+    dead_letters: Forward<M>,
+}
+
+impl<M> Queue<M> {
+    /// Lock the state. A panic in the user's [`Mailbox`] while it was locked must not take
+    /// every handle of the actor down with it, so a poisoned lock is taken over.
+    fn lock(&self) -> MutexGuard<'_, State<M>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+struct State<M> {
+    mailbox: Box<dyn Mailbox<M>>,
+    /// Number of live senders, the receiver finishes once it drops to zero.
+    senders: usize,
+    closed: bool,
+}
+
+struct QueueSender<M>(Arc<Queue<M>>);
+
+impl<M> QueueSender<M> {
+    fn upgrade(queue: &Arc<Queue<M>>) -> Option<Self> {
+        let mut state = queue.lock();
+        if state.senders == 0 {
+            return None;
+        }
+        state.senders += 1;
+        Some(QueueSender(queue.clone()))
+    }
+
+    async fn push(&self, mut msg: M) -> Result<(), SendError<M>> {
+        loop {
+            let mut writable = pin!(self.0.writable.notified());
+            writable.as_mut().enable();
+            match self.try_push(msg) {
+                Err(TrySendError::Full(returned)) => msg = returned,
+                Err(TrySendError::Closed(returned)) => return Err(SendError(returned)),
+                Ok(()) => return Ok(()),
+            }
+            writable.await;
+        }
+    }
+
+    fn try_push(&self, msg: M) -> Result<(), TrySendError<M>> {
+        let mut state = self.0.lock();
+        if state.closed {
+            return Err(TrySendError::Closed(msg));
+        }
+        let pushed = state.mailbox.push(msg);
+        drop(state);
+        match self.pushed(pushed) {
+            Some(msg) => Err(TrySendError::Full(msg)),
+            None => Ok(()),
+        }
+    }
+
+    async fn push_with<T>(&self, mut msg: T, into: impl Fn(T) -> M) -> Result<(), SendError<T>> {
+        loop {
+            let mut writable = pin!(self.0.writable.notified());
+            writable.as_mut().enable();
+            match self.try_push_with(msg, &into) {
+                Err(TrySendError::Full(returned)) => msg = returned,
+                Err(TrySendError::Closed(returned)) => return Err(SendError(returned)),
+                Ok(()) => return Ok(()),
+            }
+            writable.await;
+        }
+    }
+
+    /// Convert and push a message once the mailbox is open and has room, both checked under
+    /// the lock so the original message can be handed back.
+    fn try_push_with<T>(&self, msg: T, into: impl FnOnce(T) -> M) -> Result<(), TrySendError<T>> {
+        let mut state = self.0.lock();
+        if state.closed {
+            return Err(TrySendError::Closed(msg));
+        }
+        if state.mailbox.is_full() {
+            return Err(TrySendError::Full(msg));
+        }
+        let pushed = state.mailbox.push(into(msg));
+        drop(state);
+        if let Some(refused) = self.pushed(pushed) {
+            // The mailbox broke the contract of `Mailbox::is_full`.
+            self.0.dead_letters.send(Reason::Dropped, refused);
+        }
+        Ok(())
+    }
+
+    /// Wake the receiver for a queued message and forward a discarded one.
+    /// Returns the message if the mailbox was full.
+    fn pushed(&self, pushed: Push<M>) -> Option<M> {
+        let dropped = match pushed {
+            Push::Queued => None,
+            Push::Dropped(dropped) => Some(dropped),
+            Push::Full(msg) => return Some(msg),
+        };
+        self.0.readable.notify_one();
+        if let Some(dropped) = dropped {
+            self.0.dead_letters.send(Reason::Dropped, dropped);
+        }
+        None
+    }
+
+    fn is_closed(&self) -> bool {
+        self.0.lock().closed
+    }
+
+    fn len(&self) -> usize {
+        self.0.lock().mailbox.len()
+    }
+}
+
+impl<M> Clone for QueueSender<M> {
+    fn clone(&self) -> Self {
+        self.0.lock().senders += 1;
+        QueueSender(self.0.clone())
+    }
+}
+
+impl<M> Drop for QueueSender<M> {
+    fn drop(&mut self) {
+        let mut state = self.0.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            self.0.readable.notify_one();
+        }
+    }
+}
+
+struct QueueReceiver<M>(Arc<Queue<M>>);
+
+impl<M> QueueReceiver<M> {
+    async fn pop(&self) -> Option<M> {
+        loop {
+            let mut readable = pin!(self.0.readable.notified());
+            readable.as_mut().enable();
+            {
+                let mut state = self.0.lock();
+                if let Some(msg) = state.mailbox.pop() {
+                    drop(state);
+                    self.0.writable.notify_one();
+                    return Some(msg);
+                }
+                if state.senders == 0 || state.closed {
+                    return None;
+                }
+            }
+            readable.await;
+        }
+    }
+
+    fn try_pop(&self) -> Option<M> {
+        let msg = self.0.lock().mailbox.pop();
+        if msg.is_some() {
+            self.0.writable.notify_one();
+        }
+        msg
+    }
+
+    fn close(&self) {
+        self.0.lock().closed = true;
+        self.0.writable.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Actor, Builder, Context};
+    use std::convert::Infallible;
+    use std::panic::AssertUnwindSafe;
+    use tokio::sync::oneshot;
+
+    pub enum Message {
+        Push(u32),
+        Block(oneshot::Receiver<()>),
+    }
+
+    impl From<u32> for Message {
+        fn from(n: u32) -> Self {
+            Message::Push(n)
+        }
+    }
+
+    pub struct Collector(Vec<u32>);
+
+    impl Actor for Collector {
+        type Msg = Message;
+        type Error = Infallible;
+        async fn recv(
+            &mut self,
+            msg: Self::Msg,
+            _ctx: &mut Context<Self>,
+        ) -> Result<(), Self::Error> {
+            match msg {
+                Message::Push(n) => self.0.push(n),
+                Message::Block(release) => {
+                    let _ = release.await;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Queue pushes while the actor is blocked and return what it received.
+    async fn collect(mailbox: impl Mailbox<Message> + 'static, pushes: &[u32]) -> Vec<u32> {
+        let (h, join) = Builder::new(Collector(Vec::new()))
+            .mailbox(mailbox)
+            .spawn_with_join();
+        let (release, blocked) = oneshot::channel();
+        h.send(Message::Block(blocked)).await.unwrap();
+        while h.mailbox_len() > 0 {
+            tokio::task::yield_now().await;
+        }
+        for n in pushes {
+            h.try_send(Message::Push(*n)).unwrap();
+        }
+        release.send(()).unwrap();
+        drop(h);
+        join.await.unwrap().0
+    }
+
+    #[tokio::test]
+    async fn test_dropping() {
+        let oldest = Dropping::new(2, Overflow::DropOldest);
+        assert_eq!(collect(oldest, &[1, 2, 3, 4]).await, [3, 4]);
+        let newest = Dropping::new(2, Overflow::DropNewest);
+        assert_eq!(collect(newest, &[1, 2, 3, 4]).await, [1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_coalescing() {
+        let coalescing = Coalescing::new(|msg: &Message| match msg {
+            Message::Push(n) if *n >= 10 => Some(n / 10),
+            _ => None,
+        });
+        let pushed = collect(coalescing, &[10, 1, 11, 20, 1, 12]).await;
+        assert_eq!(pushed, [12, 1, 20, 1]);
+        assert_eq!(collect(VecDeque::new(), &[1, 2]).await, [1, 2]);
+    }
+
+    /// A custom mailbox holding a single message.
+    pub struct Slot(Option<Message>);
+
+    impl Mailbox<Message> for Slot {
+        fn push(&mut self, msg: Message) -> Push<Message> {
+            match self.0 {
+                Some(_) => Push::Full(msg),
+                None => {
+                    self.0 = Some(msg);
+                    Push::Queued
+                }
+            }
+        }
+        fn pop(&mut self) -> Option<Message> {
+            self.0.take()
+        }
+        fn len(&self) -> usize {
+            self.0.iter().len()
+        }
+        fn is_full(&self) -> bool {
+            self.0.is_some()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_mailbox_backpressure() {
+        let (h, join) = Builder::new(Collector(Vec::new()))
+            .mailbox(Slot(None))
+            .spawn_with_join();
+        let (release, blocked) = oneshot::channel();
+        h.send(Message::Block(blocked)).await.unwrap();
+        h.send(Message::Push(1)).await.unwrap();
+        assert!(matches!(
+            h.try_send(Message::Push(2)),
+            Err(TrySendError::Full(Message::Push(2)))
+        ));
+
+        let sender = h.clone();
+        let waiting = tokio::spawn(async move { sender.send(Message::Push(2)).await.is_ok() });
+        release.send(()).unwrap();
+        assert!(waiting.await.unwrap());
+
+        h.stop();
+        assert_eq!(join.await.unwrap().0, [1, 2]);
+        assert!(h.is_closed());
+        assert!(matches!(
+            h.send(Message::Push(3)).await,
+            Err(SendError(Message::Push(3)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_custom_mailbox_recipient() {
+        let (h, join) = Builder::new(Collector(Vec::new()))
+            .mailbox(Slot(None))
+            .spawn_with_join();
+        let pushes = h.recipient::<u32>();
+        let (release, blocked) = oneshot::channel();
+        h.send(Message::Block(blocked)).await.unwrap();
+        pushes.send(1).await.unwrap();
+        assert!(matches!(pushes.try_send(2), Err(TrySendError::Full(2))));
+
+        let sender = pushes.clone();
+        let waiting = tokio::spawn(async move { sender.send(2).await.is_ok() });
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        // The send waits for room instead of discarding the message.
+        assert!(!waiting.is_finished());
+        release.send(()).unwrap();
+        assert!(waiting.await.unwrap());
+
+        h.stop();
+        assert_eq!(join.await.unwrap().0, [1, 2]);
+        assert!(matches!(pushes.send(3).await, Err(SendError(3))));
+        assert!(matches!(pushes.try_send(4), Err(TrySendError::Closed(4))));
+    }
+
+    /// A custom mailbox that panics when pushed a 13.
+    pub struct Fragile(VecDeque<Message>);
+
+    impl Mailbox<Message> for Fragile {
+        fn push(&mut self, msg: Message) -> Push<Message> {
+            assert!(!matches!(msg, Message::Push(13)), "unlucky message");
+            self.0.push(msg)
+        }
+        fn pop(&mut self) -> Option<Message> {
+            self.0.pop_front()
+        }
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+        fn is_full(&self) -> bool {
+            false
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_mailbox_panic() {
+        let (h, join) = Builder::new(Collector(Vec::new()))
+            .mailbox(Fragile(VecDeque::new()))
+            .spawn_with_join();
+        let pushed = std::panic::catch_unwind(AssertUnwindSafe(|| h.try_send(Message::Push(13))));
+        assert!(pushed.is_err());
+
+        // The panic does not break the mailbox for the other messages.
+        h.send(Message::Push(1)).await.unwrap();
+        h.try_send(Message::Push(2)).unwrap();
+        drop(h);
+        assert_eq!(join.await.unwrap().0, [1, 2]);
+    }
+}
@@ -0,0 +1,411 @@
+//! Monitors and links between actors.
+
+use std::fmt;
+use std::sync::{Arc, Weak};
+
+use crate::runner::{ActorCell, Signal};
+use crate::{Actor, ActorId, Context, Handle, TrySendError, WeakHandle};
+
+/// Down is delivered to a watcher once an [`Actor`] it monitors has stopped,
+/// see [`Handle::monitor`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Down {
+    /// The actor that stopped.
+    pub id: ActorId,
+    /// Why it stopped.
+    pub reason: ExitReason,
+}
+
+/// ExitReason tells why an [`Actor`] stopped for good.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExitReason {
+    /// The mailbox was closed and drained, or the actor stopped itself.
+    Normal,
+    /// A handler or hook panicked with the given message.
+    Panicked(String),
+    /// [`Actor::recv`] returned the given error under [`ErrorPolicy::Escalate`].
+    ///
+    /// [`ErrorPolicy::Escalate`]: crate::ErrorPolicy::Escalate
+    Failed(String),
+    /// A linked actor failed, see [`Handle::link`].
+    Linked(ActorId),
+    /// The actor was dropped before it finished, for example because the runtime shut down.
+    Aborted,
+}
+
+impl ExitReason {
+    /// Returns true for every reason but [`ExitReason::Normal`].
+    /// Only a failure is propagated to linked actors.
+    pub fn is_failure(&self) -> bool {
+        *self != ExitReason::Normal
+    }
+}
+
+impl fmt::Display for ExitReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExitReason::Normal => write!(f, "stopped normally"),
+            ExitReason::Panicked(message) => write!(f, "panicked: {}", message),
+            ExitReason::Failed(error) => write!(f, "failed: {}", error),
+            ExitReason::Linked(id) => write!(f, "linked actor {} failed", id),
+            ExitReason::Aborted => write!(f, "aborted"),
+        }
+    }
+}
+
+/// MonitorRef identifies a monitor set up with [`Handle::monitor`] so it can be removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MonitorRef(u64);
+
+/// The watchers of an actor, kept in its [`ActorCell`].
+#[derive(Default)]
+pub(crate) struct Watchers {
+    /// Reason of the latest run, reported once the actor has stopped for good.
+    pub(crate) reason: Option<ExitReason>,
+    /// Set once the actor has stopped and its watchers were notified.
+    stopped: Option<ExitReason>,
+    monitors: Vec<(MonitorRef, Notify)>,
+    next: u64,
+    linked: Vec<Weak<ActorCell>>,
+}
+
+type Notify = Box<dyn Fn(Down) + Send + Sync>;
+
+impl ActorCell {
+    fn monitor<W>(&self, watcher: WeakHandle<W>) -> MonitorRef
+    where
+        W: From<Down> + Send + 'static,
+    {
+        let notify: Notify = Box::new(move |down| {
+            if let Some(watcher) = watcher.upgrade() {
+                deliver(watcher, down);
+            }
+        });
+        let mut watchers = self.watchers.lock().unwrap();
+        let monitor = MonitorRef(watchers.next);
+        watchers.next += 1;
+        match watchers.stopped.clone() {
+            None => watchers.monitors.push((monitor, notify)),
+            Some(reason) => {
+                drop(watchers);
+                notify(Down {
+                    id: self.id(),
+                    reason,
+                });
+            }
+        }
+        monitor
+    }
+
+    fn demonitor(&self, monitor: MonitorRef) -> bool {
+        let mut watchers = self.watchers.lock().unwrap();
+        let before = watchers.monitors.len();
+        watchers.monitors.retain(|(m, _)| *m != monitor);
+        watchers.monitors.len() != before
+    }
+
+    fn link(self: &Arc<Self>, other: &Arc<ActorCell>) {
+        if !Arc::ptr_eq(self, other) {
+            self.add_link(other);
+            other.add_link(self);
+        }
+    }
+
+    fn add_link(&self, peer: &Arc<ActorCell>) {
+        let mut watchers = self.watchers.lock().unwrap();
+        match &watchers.stopped {
+            None => {
+                let linked = &mut watchers.linked;
+                if !linked
+                    .iter()
+                    .any(|l| std::ptr::eq(l.as_ptr(), Arc::as_ptr(peer)))
+                {
+                    linked.push(Arc::downgrade(peer));
+                }
+            }
+            Some(reason) if reason.is_failure() => peer.signal(Signal::Linked(self.id())),
+            Some(_) => {}
+        }
+    }
+
+    fn unlink(&self, other: &ActorCell) {
+        self.remove_link(other);
+        other.remove_link(self);
+    }
+
+    fn remove_link(&self, peer: &ActorCell) {
+        let mut watchers = self.watchers.lock().unwrap();
+        watchers.linked.retain(|l| !std::ptr::eq(l.as_ptr(), peer));
+    }
+
+    /// Notify the monitors and, on a failure, the linked actors that the actor has stopped.
+    pub(crate) fn notify_stopped(&self) {
+        let mut watchers = self.watchers.lock().unwrap();
+        let reason = watchers.reason.take().unwrap_or(ExitReason::Aborted);
+        watchers.stopped = Some(reason.clone());
+        let monitors = std::mem::take(&mut watchers.monitors);
+        let linked = std::mem::take(&mut watchers.linked);
+        drop(watchers);
+
+        for (_, notify) in monitors {
+            notify(Down {
+                id: self.id(),
+                reason: reason.clone(),
+            });
+        }
+        if reason.is_failure() {
+            for peer in linked.iter().filter_map(Weak::upgrade) {
+                peer.signal(Signal::Linked(self.id()));
+            }
+        }
+    }
+}
+
+/// Send a [`Down`] without blocking the stopping actor, waiting in a task if the watcher's
+/// mailbox is full.
+fn deliver<W: From<Down> + Send + 'static>(watcher: Handle<W>, down: Down) {
+    if let Err(TrySendError::Full(msg)) = watcher.try_send(W::from(down)) {
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                let _ = watcher.send(msg).await;
+            });
+        }
+    }
+}
+
+impl<M> Handle<M> {
+    /// Monitor the [`Actor`]: once it stops for good a [`Down`] is sent to `watcher`.
+    /// If the actor has already stopped the [`Down`] is sent right away.
+    ///
+    /// The monitor does not keep the watcher alive. A supervised actor is only reported once
+    /// its supervisor stops restarting it.
+    ///
+    /// ```rust
+    /// use miniactor::{Actor, Context, Down, ExitReason, Handle};
+    /// use std::convert::Infallible;
+    ///
+    /// pub struct Worker;
+    ///
+    /// impl Actor for Worker {
+    ///     type Msg = ();
+    ///     type Error = Infallible;
+    ///
+    ///     async fn recv(&mut self, _msg: Self::Msg, _ctx: &mut Context<Self>) -> Result<(), Self::Error> {
+    ///         panic!("worker crashed");
+    ///     }
+    /// }
+    ///
+    /// pub struct Watcher;
+    ///
+    /// impl Actor for Watcher {
+    ///     type Msg = Down;
+    ///     type Error = Infallible;
+    ///
+    ///     async fn recv(&mut self, down: Self::Msg, _ctx: &mut Context<Self>) -> Result<(), Self::Error> {
+    ///         assert_eq!(down.reason, ExitReason::Panicked("worker crashed".to_string()));
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let watcher = Handle::new(Watcher);
+    ///     let worker = Handle::new(Worker);
+    ///     worker.monitor(&watcher);
+    ///     worker.send(()).await.unwrap();
+    ///     worker.stopped().await;
+    /// }
+    /// ```
+    pub fn monitor<W>(&self, watcher: &Handle<W>) -> MonitorRef
+    where
+        W: From<Down> + Send + 'static,
+    {
+        self.cell.monitor(watcher.downgrade())
+    }
+
+    /// Remove a monitor, returns false if it was already removed or has fired.
+    pub fn demonitor(&self, monitor: MonitorRef) -> bool {
+        self.cell.demonitor(monitor)
+    }
+
+    /// Link the [`Actor`] with the actor of `other`.
+    /// When either of them fails the other one fails as well with [`ExitReason::Linked`],
+    /// which in turn propagates to its own links. A normal stop is not propagated.
+    /// A supervised actor failing through a link is restarted like after any other failure.
+    ///
+    /// Like a monitor, a link only fires once an actor stops for good: a supervised actor
+    /// that crashes and is restarted does not fail its linked actors, only its final failure
+    /// once its supervisor stops restarting it does.
+    pub fn link<N>(&self, other: &Handle<N>) {
+        self.cell.link(&other.cell);
+    }
+
+    /// Remove the link between the [`Actor`] and the actor of `other`.
+    pub fn unlink<N>(&self, other: &Handle<N>) {
+        self.cell.unlink(&other.cell);
+    }
+}
+
+impl<A: Actor> Context<A> {
+    /// Monitor `target`, this actor receives a [`Down`] once it stops.
+    /// See [`Handle::monitor`].
+    pub fn monitor<N>(&self, target: &Handle<N>) -> MonitorRef
+    where
+        A::Msg: From<Down>,
+    {
+        target.cell.monitor(self.weak_handle())
+    }
+
+    /// Link this actor with `other`, see [`Handle::link`].
+    pub fn link<N>(&self, other: &Handle<N>) {
+        self.inbox.signals.cell().link(&other.cell);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Builder, Reply};
+    use std::convert::Infallible;
+
+    pub enum WatcherMessage {
+        Down(Down),
+        Take(Reply<Vec<Down>>),
+    }
+
+    impl From<Down> for WatcherMessage {
+        fn from(down: Down) -> Self {
+            WatcherMessage::Down(down)
+        }
+    }
+
+    pub struct Watcher(Vec<Down>);
+
+    impl Actor for Watcher {
+        type Msg = WatcherMessage;
+        type Error = Infallible;
+        async fn recv(
+            &mut self,
+            msg: Self::Msg,
+            _ctx: &mut Context<Self>,
+        ) -> Result<(), Self::Error> {
+            match msg {
+                WatcherMessage::Down(down) => self.0.push(down),
+                WatcherMessage::Take(reply) => reply.send(std::mem::take(&mut self.0)),
+            }
+            Ok(())
+        }
+    }
+
+    pub enum Message {
+        Panic,
+        Fail,
+    }
+
+    pub struct Worker;
+
+    impl Actor for Worker {
+        type Msg = Message;
+        type Error = String;
+        async fn recv(
+            &mut self,
+            msg: Self::Msg,
+            _ctx: &mut Context<Self>,
+        ) -> Result<(), Self::Error> {
+            match msg {
+                Message::Panic => panic!("worker panicked"),
+                Message::Fail => Err("worker failed".to_string()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_monitor() {
+        let watcher = Handle::new(Watcher(Vec::new()));
+        let panicking = Handle::new(Worker);
+        let failing = Handle::new(Worker);
+        let stopping = Handle::new(Worker);
+        let removed = Handle::new(Worker);
+        panicking.monitor(&watcher);
+        failing.monitor(&watcher);
+        stopping.monitor(&watcher);
+        let monitor = removed.monitor(&watcher);
+        assert!(removed.demonitor(monitor));
+        assert!(!removed.demonitor(monitor));
+
+        panicking.send(Message::Panic).await.unwrap();
+        panicking.stopped().await;
+        failing.send(Message::Fail).await.unwrap();
+        failing.stopped().await;
+        stopping.stop();
+        stopping.stopped().await;
+        removed.stop();
+        removed.stopped().await;
+        // Monitoring a stopped actor reports it right away.
+        failing.monitor(&watcher);
+
+        let failed = ExitReason::Failed("worker failed".to_string());
+        assert_eq!(
+            watcher.ask(WatcherMessage::Take).await.unwrap(),
+            [
+                Down {
+                    id: panicking.id(),
+                    reason: ExitReason::Panicked("worker panicked".to_string()),
+                },
+                Down {
+                    id: failing.id(),
+                    reason: failed.clone(),
+                },
+                Down {
+                    id: stopping.id(),
+                    reason: ExitReason::Normal,
+                },
+                Down {
+                    id: failing.id(),
+                    reason: failed,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_link() {
+        let watcher = Handle::new(Watcher(Vec::new()));
+        let (a, b, c) = (
+            Handle::new(Worker),
+            Handle::new(Worker),
+            Handle::new(Worker),
+        );
+        let (d, d_join) = Builder::new(Worker).spawn_with_join();
+        a.link(&b);
+        c.link(&b);
+        c.link(&d);
+        c.unlink(&d);
+        b.monitor(&watcher);
+        c.monitor(&watcher);
+
+        a.send(Message::Panic).await.unwrap();
+        c.stopped().await;
+        let reasons: Vec<_> = watcher
+            .ask(WatcherMessage::Take)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|down| down.reason)
+            .collect();
+        assert_eq!(
+            reasons,
+            [ExitReason::Linked(a.id()), ExitReason::Linked(b.id())]
+        );
+
+        // A normal stop is not propagated and unlinked actors keep running.
+        let (e, f) = (Handle::new(Worker), Handle::new(Worker));
+        e.link(&f);
+        e.stop();
+        e.stopped().await;
+        assert!(!f.is_closed());
+        assert!(!d.is_closed());
+        drop(d);
+        assert!(d_join.await.is_ok());
+    }
+}
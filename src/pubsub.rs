@@ -0,0 +1,305 @@
+//! Publish/subscribe between actors.
+//!
+//! A [`Broker`] keeps a list of subscribers per topic. Actors subscribe a
+//! [`Handle`](crate::Handle) or a [`Recipient`] and every message published to the topic is sent
+//! to each of them.
+//! Subscribers whose actor has stopped are dropped from the broker when it notices their
+//! mailbox is closed.
+//!
+//! The broker does not keep its subscribers alive: once every other [`Handle`](crate::Handle)
+//! of a subscribed actor is dropped the actor stops and its subscriptions are pruned.
+//! [`Broker::subscribe_strong`] keeps the actor running until it is stopped or unsubscribed.
+//!
+//! ```rust
+//! use miniactor::pubsub::Broker;
+//! use miniactor::{Actor, Context, Handle};
+//! use std::convert::Infallible;
+//!
+//! pub struct Printer(&'static str);
+//!
+//! impl Actor for Printer {
+//!     type Msg = String;
+//!     type Error = Infallible;
+//!
+//!     async fn recv(&mut self, msg: Self::Msg, _ctx: &mut Context<Self>) -> Result<(), Self::Error> {
+//!         println!("{}: {}", self.0, msg);
+//!         Ok(())
+//!     }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let broker = Broker::new();
+//!     let first = Handle::new(Printer("first"));
+//!     broker.subscribe("news", first.clone());
+//!     broker.subscribe_strong("news", Handle::new(Printer("second")));
+//!
+//!     let delivered = broker.publish("news", "hello".to_string()).await;
+//!     assert_eq!(delivered, 2);
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::{Recipient, TrySendError, WeakRecipient};
+
+/// SubscriptionId identifies a subscription made with [`Broker::subscribe`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+struct Subscriber<T> {
+    id: SubscriptionId,
+    recipient: WeakRecipient<T>,
+    /// Keeps the actor alive for a strong subscription.
+    strong: Option<Recipient<T>>,
+}
+
+impl<T: Send + 'static> Subscriber<T> {
+    /// Returns the recipient if the actor is still running.
+    fn live(&self) -> Option<Recipient<T>> {
+        self.strong
+            .clone()
+            .or_else(|| self.recipient.upgrade())
+            .filter(|recipient| !recipient.is_closed())
+    }
+}
+
+struct Topics<T> {
+    topics: HashMap<String, Vec<Subscriber<T>>>,
+    next_id: u64,
+}
+
+/// Broker broadcasts messages of type `T` to the subscribers of a topic.
+/// It can be cloned and passed around, clones share the same subscriptions.
+pub struct Broker<T> {
+    topics: Arc<Mutex<Topics<T>>>,
+}
+
+impl<T: Clone + Send + 'static> Broker<T> {
+    /// Create a broker without any subscriptions.
+    pub fn new() -> Self {
+        Broker {
+            topics: Arc::new(Mutex::new(Topics {
+                topics: HashMap::new(),
+                next_id: 0,
+            })),
+        }
+    }
+
+    /// Subscribe to `topic` without keeping the subscriber alive.
+    /// Accepts a [`Handle`](crate::Handle) whose message type can be built from `T`, or a
+    /// [`Recipient<T>`].
+    pub fn subscribe(
+        &self,
+        topic: impl Into<String>,
+        subscriber: impl Into<Recipient<T>>,
+    ) -> SubscriptionId {
+        self.add(topic.into(), subscriber.into(), false)
+    }
+
+    /// Subscribe to `topic`, the broker keeps the subscriber running until it is stopped or
+    /// unsubscribed.
+    pub fn subscribe_strong(
+        &self,
+        topic: impl Into<String>,
+        subscriber: impl Into<Recipient<T>>,
+    ) -> SubscriptionId {
+        self.add(topic.into(), subscriber.into(), true)
+    }
+
+    fn add(&self, topic: String, recipient: Recipient<T>, strong: bool) -> SubscriptionId {
+        let mut topics = self.topics.lock().unwrap();
+        let id = SubscriptionId(topics.next_id);
+        topics.next_id += 1;
+        topics.topics.entry(topic).or_default().push(Subscriber {
+            id,
+            recipient: recipient.downgrade(),
+            strong: strong.then_some(recipient),
+        });
+        id
+    }
+
+    /// Remove a subscription, returns false if it was already removed or pruned.
+    pub fn unsubscribe(&self, topic: &str, id: SubscriptionId) -> bool {
+        let mut topics = self.topics.lock().unwrap();
+        let Some(subscribers) = topics.topics.get_mut(topic) else {
+            return false;
+        };
+        let before = subscribers.len();
+        subscribers.retain(|s| s.id != id);
+        let removed = subscribers.len() != before;
+        if subscribers.is_empty() {
+            topics.topics.remove(topic);
+        }
+        removed
+    }
+
+    /// Send `msg` to every subscriber of `topic`, waiting for room in full mailboxes.
+    /// Returns how many subscribers the message was delivered to.
+    pub async fn publish(&self, topic: &str, msg: T) -> usize {
+        let mut delivered = 0;
+        let mut closed = Vec::new();
+        for (id, recipient) in self.recipients(topic) {
+            match recipient.send(msg.clone()).await {
+                Ok(()) => delivered += 1,
+                Err(_) => closed.push(id),
+            }
+        }
+        self.prune(topic, &closed);
+        delivered
+    }
+
+    /// Send `msg` to every subscriber of `topic` without waiting.
+    /// Subscribers with a full mailbox miss the message.
+    /// Returns how many subscribers the message was delivered to.
+    pub fn try_publish(&self, topic: &str, msg: T) -> usize {
+        let mut delivered = 0;
+        let mut closed = Vec::new();
+        for (id, recipient) in self.recipients(topic) {
+            match recipient.try_send(msg.clone()) {
+                Ok(()) => delivered += 1,
+                Err(TrySendError::Closed(_)) => closed.push(id),
+                Err(TrySendError::Full(_)) => {}
+            }
+        }
+        self.prune(topic, &closed);
+        delivered
+    }
+
+    /// The number of live subscribers of `topic`.
+    pub fn subscribers(&self, topic: &str) -> usize {
+        self.prune(topic, &[]);
+        let topics = self.topics.lock().unwrap();
+        topics.topics.get(topic).map_or(0, Vec::len)
+    }
+
+    /// Copy the subscribers out so no lock is held while sending.
+    fn recipients(&self, topic: &str) -> Vec<(SubscriptionId, Recipient<T>)> {
+        let topics = self.topics.lock().unwrap();
+        topics
+            .topics
+            .get(topic)
+            .map_or_else(Vec::new, |subscribers| {
+                subscribers
+                    .iter()
+                    .filter_map(|s| Some((s.id, s.live()?)))
+                    .collect()
+            })
+    }
+
+    /// Drop the given subscriptions and any whose actor has stopped.
+    fn prune(&self, topic: &str, closed: &[SubscriptionId]) {
+        let mut topics = self.topics.lock().unwrap();
+        let Some(subscribers) = topics.topics.get_mut(topic) else {
+            return;
+        };
+        subscribers.retain(|s| !closed.contains(&s.id) && s.live().is_some());
+        if subscribers.is_empty() {
+            topics.topics.remove(topic);
+        }
+    }
+}
+
+impl<T: Clone + Send + 'static> Default for Broker<T> {
+    fn default() -> Self {
+        Broker::new()
+    }
+}
+
+impl<T> Clone for Broker<T> {
+    fn clone(&self) -> Self {
+        Broker {
+            topics: self.topics.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Actor, Builder, Context, Handle, Reply};
+    use std::convert::Infallible;
+
+    pub enum Message {
+        Event(u32),
+        Seen(Reply<Vec<u32>>),
+    }
+
+    impl From<u32> for Message {
+        fn from(n: u32) -> Self {
+            Message::Event(n)
+        }
+    }
+
+    pub struct Listener(Vec<u32>);
+
+    impl Actor for Listener {
+        type Msg = Message;
+        type Error = Infallible;
+        async fn recv(
+            &mut self,
+            msg: Self::Msg,
+            _ctx: &mut Context<Self>,
+        ) -> Result<(), Self::Error> {
+            match msg {
+                Message::Event(n) => self.0.push(n),
+                Message::Seen(reply) => reply.send(self.0.clone()),
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publish() {
+        let broker = Broker::<u32>::new();
+        let a = Handle::new(Listener(Vec::new()));
+        let b = Handle::new(Listener(Vec::new()));
+        broker.subscribe("numbers", a.clone());
+        let id = broker.subscribe("numbers", b.recipient());
+        broker.subscribe("other", b.clone());
+
+        assert_eq!(broker.publish("numbers", 1).await, 2);
+        assert_eq!(broker.clone().try_publish("other", 2), 1);
+        assert!(broker.unsubscribe("numbers", id));
+        assert!(!broker.unsubscribe("numbers", id));
+        assert_eq!(broker.publish("numbers", 3).await, 1);
+        assert_eq!(broker.publish("nobody", 4).await, 0);
+
+        assert_eq!(a.ask(Message::Seen).await, Ok(vec![1, 3]));
+        assert_eq!(b.ask(Message::Seen).await, Ok(vec![1, 2]));
+    }
+
+    #[tokio::test]
+    async fn test_prune_stopped_subscribers() {
+        let broker = Broker::<u32>::new();
+        let (a, join) = Builder::new(Listener(Vec::new())).spawn_with_join();
+        let b = Handle::new(Listener(Vec::new()));
+        broker.subscribe("numbers", a.clone());
+        broker.subscribe("numbers", b.clone());
+        assert_eq!(broker.subscribers("numbers"), 2);
+
+        a.stop();
+        join.await.unwrap();
+        assert_eq!(broker.publish("numbers", 1).await, 1);
+        assert_eq!(broker.subscribers("numbers"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_subscriber_lifetime() {
+        let broker = Broker::<u32>::new();
+        let (weak, weak_join) = Builder::new(Listener(Vec::new())).spawn_with_join();
+        let (strong, strong_join) = Builder::new(Listener(Vec::new())).spawn_with_join();
+        broker.subscribe("numbers", weak);
+        let id = broker.subscribe_strong("numbers", strong);
+
+        // Dropping the only handle stops a weakly subscribed actor.
+        weak_join.await.unwrap();
+        assert_eq!(broker.subscribers("numbers"), 1);
+        assert_eq!(broker.publish("numbers", 1).await, 1);
+
+        assert!(broker.unsubscribe("numbers", id));
+        let listener = strong_join.await.unwrap();
+        assert_eq!(listener.0, [1]);
+    }
+}
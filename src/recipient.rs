@@ -0,0 +1,278 @@
+//! Type erased senders accepting a single message type.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use tokio::sync::oneshot;
+
+use crate::{AskError, Handle, Reply, SendError, TrySendError, WeakHandle};
+
+type SendFuture<'a, T> = Pin<Box<dyn Future<Output = Result<(), SendError<T>>> + Send + 'a>>;
+
+/// Recipient sends messages of type `T` to an [`Actor`](crate::Actor) whose message type can
+/// be built from `T`.
+/// It hides the actor's full message type, so a producer only depends on the messages it
+/// actually sends.
+///
+/// ```rust
+/// use miniactor::{Actor, Context, Handle, Recipient};
+/// use std::convert::Infallible;
+///
+/// pub struct Tick;
+///
+/// pub enum Message {
+///     Tick(Tick),
+///     Reset,
+/// }
+///
+/// impl From<Tick> for Message {
+///     fn from(tick: Tick) -> Self {
+///         Message::Tick(tick)
+///     }
+/// }
+///
+/// pub struct Counter(u32);
+///
+/// impl Actor for Counter {
+///     type Msg = Message;
+///     type Error = Infallible;
+///
+///     async fn recv(&mut self, msg: Self::Msg, _ctx: &mut Context<Self>) -> Result<(), Self::Error> {
+///         match msg {
+///             Message::Tick(_) => self.0 += 1,
+///             Message::Reset => self.0 = 0,
+///         }
+///         Ok(())
+///     }
+/// }
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let h = Handle::new(Counter(0));
+///     let ticks: Recipient<Tick> = h.recipient();
+///     ticks.send(Tick).await.unwrap();
+/// }
+/// ```
+pub struct Recipient<T> {
+    sink: Arc<dyn Sink<T>>,
+}
+
+/// Object safe view of a [`Handle`] accepting messages of type `T`.
+trait Sink<T>: Send + Sync {
+    fn send(&self, msg: T) -> SendFuture<'_, T>;
+    fn try_send(&self, msg: T) -> Result<(), TrySendError<T>>;
+    fn is_closed(&self) -> bool;
+    fn downgrade(&self) -> Arc<dyn WeakSink<T>>;
+}
+
+/// Object safe view of a [`WeakHandle`] accepting messages of type `T`.
+trait WeakSink<T>: Send + Sync {
+    fn upgrade(&self) -> Option<Recipient<T>>;
+}
+
+impl<M, T> Sink<T> for Handle<M>
+where
+    M: From<T> + Send + 'static,
+    T: Send + 'static,
+{
+    fn send(&self, msg: T) -> SendFuture<'_, T> {
+        Box::pin(self.sender.send_with(msg, M::from))
+    }
+
+    fn try_send(&self, msg: T) -> Result<(), TrySendError<T>> {
+        self.sender.try_send_with(msg, M::from)
+    }
+
+    fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+
+    fn downgrade(&self) -> Arc<dyn WeakSink<T>> {
+        Arc::new(Handle::downgrade(self))
+    }
+}
+
+impl<M, T> WeakSink<T> for WeakHandle<M>
+where
+    M: From<T> + Send + 'static,
+    T: Send + 'static,
+{
+    fn upgrade(&self) -> Option<Recipient<T>> {
+        WeakHandle::upgrade(self).map(Recipient::from)
+    }
+}
+
+impl<T: Send + 'static> Recipient<T> {
+    /// Send a message, waiting for room if the mailbox is full.
+    /// Fails if the actor has stopped, handing the message back in the [`SendError`].
+    pub async fn send(&self, msg: T) -> Result<(), SendError<T>> {
+        self.sink.send(msg).await
+    }
+
+    /// Send a message without waiting.
+    /// Fails if the mailbox is full or the actor has stopped.
+    pub fn try_send(&self, msg: T) -> Result<(), TrySendError<T>> {
+        self.sink.try_send(msg)
+    }
+
+    /// Returns true if the actor has stopped and no longer accepts messages.
+    pub fn is_closed(&self) -> bool {
+        self.sink.is_closed()
+    }
+
+    /// Send a request and wait for its reply, see [`Handle::ask`].
+    pub async fn ask<R>(&self, make_msg: impl FnOnce(Reply<R>) -> T) -> Result<R, AskError> {
+        let (sender, receiver) = oneshot::channel();
+        self.send(make_msg(Reply(sender)))
+            .await
+            .map_err(|_| AskError::Closed)?;
+        receiver.await.map_err(|_| AskError::Dropped)
+    }
+
+    /// Create a [`WeakRecipient`] that does not keep the actor alive.
+    pub fn downgrade(&self) -> WeakRecipient<T> {
+        WeakRecipient {
+            sink: self.sink.downgrade(),
+        }
+    }
+}
+
+impl<T> Clone for Recipient<T> {
+    fn clone(&self) -> Self {
+        Recipient {
+            sink: self.sink.clone(),
+        }
+    }
+}
+
+/// WeakRecipient is a [`Recipient`] that does not keep the [`Actor`](crate::Actor) alive,
+/// created with [`Recipient::downgrade`].
+/// Like a [`WeakHandle`] it has to be upgraded before messages can be sent.
+pub struct WeakRecipient<T> {
+    sink: Arc<dyn WeakSink<T>>,
+}
+
+impl<T> WeakRecipient<T> {
+    /// Get a [`Recipient`] back, returns `None` once every [`Handle`] has been dropped.
+    pub fn upgrade(&self) -> Option<Recipient<T>> {
+        self.sink.upgrade()
+    }
+}
+
+impl<T> Clone for WeakRecipient<T> {
+    fn clone(&self) -> Self {
+        WeakRecipient {
+            sink: self.sink.clone(),
+        }
+    }
+}
+
+impl<M, T> From<Handle<M>> for Recipient<T>
+where
+    M: From<T> + Send + 'static,
+    T: Send + 'static,
+{
+    fn from(handle: Handle<M>) -> Self {
+        Recipient {
+            sink: Arc::new(handle),
+        }
+    }
+}
+
+impl<M: Send + 'static> Handle<M> {
+    /// Create a [`Recipient`] accepting any message the actor's message type can be built from.
+    /// The recipient keeps the actor alive like a [`Handle`].
+    pub fn recipient<T>(&self) -> Recipient<T>
+    where
+        M: From<T>,
+        T: Send + 'static,
+    {
+        self.clone().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Actor, Builder, Context};
+    use std::convert::Infallible;
+
+    pub struct Push(u32);
+
+    pub enum Message {
+        Push(u32),
+        Sum(Reply<u32>),
+    }
+
+    impl From<Push> for Message {
+        fn from(Push(n): Push) -> Self {
+            Message::Push(n)
+        }
+    }
+
+    pub struct Summer(u32);
+
+    impl Actor for Summer {
+        type Msg = Message;
+        type Error = Infallible;
+        async fn recv(
+            &mut self,
+            msg: Self::Msg,
+            _ctx: &mut Context<Self>,
+        ) -> Result<(), Self::Error> {
+            match msg {
+                Message::Push(n) => self.0 += n,
+                Message::Sum(reply) => reply.send(self.0),
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recipient() {
+        let h = Handle::new(Summer(0));
+        let pushes: Recipient<Push> = h.recipient();
+        pushes.send(Push(1)).await.unwrap();
+        pushes.clone().try_send(Push(2)).unwrap();
+
+        let all: Recipient<Message> = h.clone().into();
+        assert_eq!(all.ask(Message::Sum).await, Ok(3));
+    }
+
+    #[tokio::test]
+    async fn test_weak_recipient() {
+        let (h, join) = Builder::new(Summer(0)).spawn_with_join();
+        let pushes = h.recipient::<Push>();
+        let weak = pushes.downgrade();
+        weak.upgrade().unwrap().send(Push(1)).await.unwrap();
+
+        drop((h, pushes));
+        join.await.unwrap();
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_recipient_closed_returns_message() {
+        for capacity in [None, Some(1)] {
+            let mut builder = Builder::new(Summer(0));
+            if let Some(capacity) = capacity {
+                builder = builder.bounded(capacity);
+            }
+            let (h, join) = builder.spawn_with_join();
+            let pushes = h.recipient::<Push>();
+            h.stop();
+            join.await.unwrap();
+
+            assert!(pushes.is_closed());
+            assert!(matches!(
+                pushes.send(Push(4)).await,
+                Err(SendError(Push(4)))
+            ));
+            assert!(matches!(
+                pushes.try_send(Push(5)),
+                Err(TrySendError::Closed(Push(5)))
+            ));
+        }
+    }
+}
@@ -0,0 +1,305 @@
+//! Registry for finding actors by name.
+//!
+//! A [`Handle`] can be registered under a string name or a typed [`Key`] and looked up from
+//! anywhere in the program. A name can only be held by one running actor at a time, and it is
+//! released automatically when that actor stops.
+//!
+//! The registry holds a [`Handle`] to every registered actor, so a registered actor keeps
+//! running after all other handles are dropped until it is stopped or unregistered.
+//!
+//! Every running actor can also be found by its path with [`resolve`], without registering it.
+//!
+//! ```rust
+//! use miniactor::registry::{self, Key};
+//! use miniactor::{Actor, Context, Handle};
+//! use std::convert::Infallible;
+//!
+//! pub struct Logger;
+//!
+//! impl Actor for Logger {
+//!     type Msg = String;
+//!     type Error = Infallible;
+//!
+//!     async fn recv(&mut self, msg: Self::Msg, _ctx: &mut Context<Self>) -> Result<(), Self::Error> {
+//!         println!("{}", msg);
+//!         Ok(())
+//!     }
+//! }
+//!
+//! const LOGGER: Key<String> = Key::new("logger");
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     LOGGER.register(&Handle::new(Logger)).unwrap();
+//!
+//!     let logger = LOGGER.lookup().unwrap();
+//!     logger.send("hello".to_string()).await.unwrap();
+//!
+//!     // Plain names work too, the message type is checked on lookup.
+//!     assert!(registry::lookup::<String>("logger").is_some());
+//!     assert!(registry::lookup::<u32>("logger").is_none());
+//! }
+//! ```
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::{Arc, LazyLock, Mutex};
+
+use crate::runner::ActorCell;
+use crate::{Handle, RegistryError, WeakHandle};
+
+struct Entry {
+    cell: Arc<ActorCell>,
+    handle: Box<dyn Any + Send + Sync>,
+}
+
+static REGISTRY: LazyLock<Mutex<HashMap<String, Entry>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Every running actor by path, holding a [`WeakHandle`].
+static PATHS: LazyLock<Mutex<HashMap<String, Entry>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Key is a name tied to the message type of the actor registered under it,
+/// so lookups through the key need no type annotations.
+pub struct Key<M> {
+    name: &'static str,
+    _msg: PhantomData<fn() -> M>,
+}
+
+impl<M: Send + 'static> Key<M> {
+    /// Create a key for the given name.
+    pub const fn new(name: &'static str) -> Self {
+        Key {
+            name,
+            _msg: PhantomData,
+        }
+    }
+
+    /// The name the key registers under.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Register the handle under this key. See [`register`].
+    pub fn register(&self, handle: &Handle<M>) -> Result<(), RegistryError> {
+        register(self.name, handle)
+    }
+
+    /// Look up the handle registered under this key. See [`lookup`].
+    pub fn lookup(&self) -> Option<Handle<M>> {
+        lookup(self.name)
+    }
+}
+
+/// Register the handle under `name`.
+/// Fails if the name is held by another running actor or if the actor has already stopped.
+pub fn register<M: Send + 'static>(
+    name: impl Into<String>,
+    handle: &Handle<M>,
+) -> Result<(), RegistryError> {
+    let name = name.into();
+    let mut registry = REGISTRY.lock().unwrap();
+    if registry.contains_key(&name) {
+        return Err(RegistryError::AlreadyRegistered);
+    }
+    match handle.cell.names.lock().unwrap().as_mut() {
+        Some(names) => names.push(name.clone()),
+        None => return Err(RegistryError::NotRunning),
+    }
+    registry.insert(
+        name,
+        Entry {
+            cell: handle.cell.clone(),
+            handle: Box::new(handle.clone()),
+        },
+    );
+    Ok(())
+}
+
+/// Look up the handle registered under `name`.
+/// Returns `None` if no actor is registered under the name or its message type is not `M`.
+pub fn lookup<M: Send + 'static>(name: &str) -> Option<Handle<M>> {
+    let registry = REGISTRY.lock().unwrap();
+    registry.get(name)?.handle.downcast_ref().cloned()
+}
+
+/// Remove the registration for `name`, returns false if nothing was registered.
+pub fn unregister(name: &str) -> bool {
+    let mut registry = REGISTRY.lock().unwrap();
+    let Some(entry) = registry.remove(name) else {
+        return false;
+    };
+    if let Some(names) = entry.cell.names.lock().unwrap().as_mut() {
+        names.retain(|n| n != name);
+    }
+    true
+}
+
+/// Resolve the actor running at `path`, such as `/system/ingest/worker-3`.
+/// Every actor has a path made of its parent's path and its name, see [`Builder::name`] and
+/// [`Builder::child_of`]. Unlike a registered name a path does not keep the actor alive.
+/// Returns `None` if no actor is running at the path or its message type is not `M`.
+///
+/// [`Builder::name`]: crate::Builder::name
+/// [`Builder::child_of`]: crate::Builder::child_of
+pub fn resolve<M: Send + 'static>(path: &str) -> Option<Handle<M>> {
+    let paths = PATHS.lock().unwrap();
+    paths
+        .get(path)?
+        .handle
+        .downcast_ref::<WeakHandle<M>>()?
+        .upgrade()
+}
+
+/// Make a newly spawned actor resolvable by its path.
+/// Fails if a running actor already has the same path.
+pub(crate) fn bind_path<M: Send + 'static>(handle: &Handle<M>) -> Result<(), RegistryError> {
+    let path = handle.cell.path();
+    let mut paths = PATHS.lock().unwrap();
+    if paths.contains_key(path) {
+        return Err(RegistryError::AlreadyRegistered);
+    }
+    paths.insert(
+        path.to_string(),
+        Entry {
+            cell: handle.cell.clone(),
+            handle: Box::new(handle.downgrade()),
+        },
+    );
+    Ok(())
+}
+
+/// Returns true if `name` can be a segment of a path: it is non-empty, does not contain a `/`
+/// and does not have the form `actor-<n>` of the names of unnamed actors.
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    let reserved = name
+        .strip_prefix("actor-")
+        .is_some_and(|id| !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()));
+    !name.is_empty() && !name.contains('/') && !reserved
+}
+
+/// Release every name and the path held by a stopped actor and refuse new registrations for
+/// it.
+pub(crate) fn release(cell: &ActorCell) {
+    let mut registry = REGISTRY.lock().unwrap();
+    if let Some(names) = cell.names.lock().unwrap().take() {
+        for name in names {
+            registry.remove(&name);
+        }
+    }
+    drop(registry);
+    let mut paths = PATHS.lock().unwrap();
+    if paths
+        .get(cell.path())
+        .is_some_and(|entry| std::ptr::eq(Arc::as_ptr(&entry.cell), cell))
+    {
+        paths.remove(cell.path());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Actor, ActorSystem, Builder, Context, Reply};
+    use std::convert::Infallible;
+
+    pub struct Echo;
+
+    impl Actor for Echo {
+        type Msg = u32;
+        type Error = Infallible;
+        async fn recv(
+            &mut self,
+            _msg: Self::Msg,
+            _ctx: &mut Context<Self>,
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_register_lookup() {
+        const KEY: Key<u32> = Key::new("test_register_lookup");
+        let h = Handle::new(Echo);
+        KEY.register(&h).unwrap();
+        assert!(KEY.lookup().is_some());
+        assert!(lookup::<String>(KEY.name()).is_none());
+        assert_eq!(
+            register(KEY.name(), &Handle::new(Echo)),
+            Err(RegistryError::AlreadyRegistered)
+        );
+
+        assert!(unregister(KEY.name()));
+        assert!(!unregister(KEY.name()));
+        assert!(KEY.lookup().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_released_on_stop() {
+        let h = Handle::new(Echo);
+        register("test_released_on_stop", &h).unwrap();
+        h.stop();
+        h.stopped().await;
+        assert!(lookup::<u32>("test_released_on_stop").is_none());
+        assert_eq!(
+            register("test_released_on_stop", &h),
+            Err(RegistryError::NotRunning)
+        );
+    }
+
+    pub enum Message {
+        Spawn(&'static str, Reply<Handle<u32>>),
+    }
+
+    pub struct Ingest;
+
+    impl Actor for Ingest {
+        type Msg = Message;
+        type Error = Infallible;
+        async fn recv(
+            &mut self,
+            msg: Self::Msg,
+            ctx: &mut Context<Self>,
+        ) -> Result<(), Self::Error> {
+            match msg {
+                Message::Spawn(name, reply) => {
+                    reply.send(Builder::new(Echo).name(name).child_of(ctx).spawn());
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_path() {
+        let system = ActorSystem::named("test_resolve_path");
+        let ingest = Builder::new(Ingest).name("ingest").system(&system).spawn();
+        let worker = ingest
+            .ask(|reply| Message::Spawn("worker-3", reply))
+            .await
+            .unwrap();
+        assert_eq!(ingest.path(), "/test_resolve_path/ingest");
+        assert_eq!(worker.path(), "/test_resolve_path/ingest/worker-3");
+
+        let resolved = resolve::<u32>("/test_resolve_path/ingest/worker-3").unwrap();
+        assert_eq!(resolved.id(), worker.id());
+        assert!(resolve::<String>(worker.path()).is_none());
+        assert!(resolve::<Message>(ingest.path()).is_some());
+        let taken = Builder::new(Ingest)
+            .name("ingest")
+            .system(&system)
+            .try_spawn();
+        assert!(matches!(taken, Err(RegistryError::AlreadyRegistered)));
+        let reserved = Builder::new(Echo).name("actor-10").try_spawn();
+        assert!(matches!(reserved, Err(RegistryError::InvalidName)));
+
+        // Stopping the parent stops its children and frees their paths.
+        ingest.stop();
+        ingest.stopped().await;
+        assert!(worker.is_closed());
+        assert!(resolve::<u32>(worker.path()).is_none());
+        assert!(resolve::<Message>(ingest.path()).is_none());
+    }
+}
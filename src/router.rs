@@ -0,0 +1,469 @@
+//! Routers spreading messages over a pool of actors.
+//!
+//! A [`Router`] offers the same send API as a [`Handle`] and forwards every message to one or
+//! all of its routees, chosen by its [`Routing`]. A router created with [`Router::pool`] spawns
+//! its routees from a factory and can be resized at runtime.
+//! Routees whose actor has stopped are removed when the router next sends to them.
+//!
+//! ```rust
+//! use miniactor::{Actor, Context, Router, Routing};
+//! use std::convert::Infallible;
+//!
+//! pub struct Worker;
+//!
+//! impl Actor for Worker {
+//!     type Msg = u32;
+//!     type Error = Infallible;
+//!
+//!     async fn recv(&mut self, msg: Self::Msg, _ctx: &mut Context<Self>) -> Result<(), Self::Error> {
+//!         println!("working on {}", msg);
+//!         Ok(())
+//!     }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let router = Router::pool(Routing::round_robin(), 4, || Worker);
+//!     for n in 0..8 {
+//!         router.send(n).await.unwrap();
+//!     }
+//!
+//!     router.resize(2);
+//!     assert_eq!(router.len(), 2);
+//!     router.stop();
+//! }
+//! ```
+
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::oneshot;
+
+use crate::{Actor, AskError, Handle, Reply, SendError, TrySendError};
+
+/// Routing decides which routee of a [`Router`] receives a message.
+pub struct Routing<M>(Kind<M>);
+
+enum Kind<M> {
+    RoundRobin,
+    Random,
+    LeastLoaded,
+    ConsistentHash(Box<dyn Fn(&M) -> u64 + Send + Sync>),
+    Broadcast(fn(&M) -> M),
+}
+
+impl<M> Routing<M> {
+    /// Send to each routee in turn.
+    pub fn round_robin() -> Self {
+        Routing(Kind::RoundRobin)
+    }
+
+    /// Send to a routee picked at random.
+    pub fn random() -> Self {
+        Routing(Kind::Random)
+    }
+
+    /// Send to the routee with the fewest messages waiting in its mailbox.
+    pub fn least_loaded() -> Self {
+        Routing(Kind::LeastLoaded)
+    }
+
+    /// Send every message with the same key to the same routee.
+    /// When routees are added or removed only the keys of the changed routees move.
+    pub fn consistent_hash<K, F>(key: F) -> Self
+    where
+        K: Hash,
+        F: Fn(&M) -> K + Send + Sync + 'static,
+    {
+        Routing(Kind::ConsistentHash(Box::new(move |msg| {
+            let mut hasher = DefaultHasher::new();
+            key(msg).hash(&mut hasher);
+            hasher.finish()
+        })))
+    }
+
+    /// Send a copy of every message to all routees.
+    pub fn broadcast() -> Self
+    where
+        M: Clone,
+    {
+        Routing(Kind::Broadcast(M::clone))
+    }
+}
+
+/// Router forwards messages to a pool of actors according to its [`Routing`].
+/// It can be cloned and passed around, clones share the same routees.
+pub struct Router<M> {
+    inner: Arc<Inner<M>>,
+}
+
+type Factory<M> = Box<dyn Fn() -> Handle<M> + Send + Sync>;
+
+struct Inner<M> {
+    routing: Routing<M>,
+    routees: Mutex<Vec<Handle<M>>>,
+    next: AtomicUsize,
+    factory: Option<Factory<M>>,
+}
+
+impl<M: Send + 'static> Router<M> {
+    /// Create a router over existing handles.
+    pub fn new(routing: Routing<M>, routees: impl IntoIterator<Item = Handle<M>>) -> Self {
+        Router::build(routing, routees.into_iter().collect(), None)
+    }
+
+    /// Spawn `size` actors built by `factory` and route over them.
+    /// The factory is kept to spawn more actors when the router is resized.
+    pub fn pool<T, F>(routing: Routing<M>, size: usize, factory: F) -> Self
+    where
+        T: Actor<Msg = M>,
+        F: Fn() -> T + Send + Sync + 'static,
+    {
+        let factory: Factory<M> = Box::new(move || Handle::new(factory()));
+        let routees = (0..size).map(|_| factory()).collect();
+        Router::build(routing, routees, Some(factory))
+    }
+
+    fn build(routing: Routing<M>, routees: Vec<Handle<M>>, factory: Option<Factory<M>>) -> Self {
+        Router {
+            inner: Arc::new(Inner {
+                routing,
+                routees: Mutex::new(routees),
+                next: AtomicUsize::new(0),
+                factory,
+            }),
+        }
+    }
+
+    /// Send a message to the chosen routee, waiting for room if its mailbox is full.
+    /// A broadcast succeeds if at least one routee received the message.
+    /// Fails if every routee has stopped, handing the message back in the [`SendError`].
+    pub async fn send(&self, mut msg: M) -> Result<(), SendError<M>> {
+        if let Kind::Broadcast(copy) = &self.inner.routing.0 {
+            let mut delivered = false;
+            for routee in self.routees() {
+                delivered |= routee.send(copy(&msg)).await.is_ok();
+            }
+            self.prune();
+            return if delivered {
+                Ok(())
+            } else {
+                Err(SendError(msg))
+            };
+        }
+        while let Some(routee) = self.pick(&msg) {
+            match routee.send(msg).await {
+                Ok(()) => return Ok(()),
+                Err(SendError(returned)) => {
+                    msg = returned;
+                    self.prune();
+                }
+            }
+        }
+        Err(SendError(msg))
+    }
+
+    /// Send a message to the chosen routee without waiting.
+    /// Fails if its mailbox is full or every routee has stopped.
+    pub fn try_send(&self, mut msg: M) -> Result<(), TrySendError<M>> {
+        if let Kind::Broadcast(copy) = &self.inner.routing.0 {
+            let mut delivered = false;
+            let mut full = false;
+            for routee in self.routees() {
+                match routee.try_send(copy(&msg)) {
+                    Ok(()) => delivered = true,
+                    Err(TrySendError::Full(_)) => full = true,
+                    Err(TrySendError::Closed(_)) => {}
+                }
+            }
+            self.prune();
+            return match (delivered, full) {
+                (true, _) => Ok(()),
+                (false, true) => Err(TrySendError::Full(msg)),
+                (false, false) => Err(TrySendError::Closed(msg)),
+            };
+        }
+        while let Some(routee) = self.pick(&msg) {
+            match routee.try_send(msg) {
+                Err(TrySendError::Closed(returned)) => {
+                    msg = returned;
+                    self.prune();
+                }
+                result => return result,
+            }
+        }
+        Err(TrySendError::Closed(msg))
+    }
+
+    /// Send a request to the chosen routee and wait for its reply, see [`Handle::ask`].
+    pub async fn ask<R>(&self, make_msg: impl FnOnce(Reply<R>) -> M) -> Result<R, AskError> {
+        let (sender, receiver) = oneshot::channel();
+        self.send(make_msg(Reply(sender)))
+            .await
+            .map_err(|_| AskError::Closed)?;
+        receiver.await.map_err(|_| AskError::Dropped)
+    }
+
+    /// Returns true if every routee has stopped.
+    pub fn is_closed(&self) -> bool {
+        self.prune();
+        self.inner.routees.lock().unwrap().is_empty()
+    }
+
+    /// The number of routees.
+    pub fn len(&self) -> usize {
+        self.inner.routees.lock().unwrap().len()
+    }
+
+    /// Returns true if the router has no routees.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Handles to the current routees.
+    pub fn routees(&self) -> Vec<Handle<M>> {
+        self.inner.routees.lock().unwrap().clone()
+    }
+
+    /// Add an existing actor to the routees.
+    pub fn add(&self, routee: Handle<M>) {
+        self.inner.routees.lock().unwrap().push(routee);
+    }
+
+    /// Grow or shrink the pool to `size` routees.
+    /// Routees removed from the pool are stopped after draining their mailbox.
+    /// Only a router created with [`Router::pool`] can grow, others keep their size when
+    /// asked to grow. Returns the new number of routees.
+    pub fn resize(&self, size: usize) -> usize {
+        let mut routees = self.inner.routees.lock().unwrap();
+        routees.retain(|routee| !routee.is_closed());
+        if let Some(factory) = &self.inner.factory {
+            while routees.len() < size {
+                routees.push(factory());
+            }
+        }
+        let keep = size.min(routees.len());
+        for routee in routees.drain(keep..) {
+            routee.stop();
+        }
+        routees.len()
+    }
+
+    /// Stop every routee, see [`Handle::stop`].
+    pub fn stop(&self) {
+        for routee in self.inner.routees.lock().unwrap().drain(..) {
+            routee.stop();
+        }
+    }
+
+    /// Choose the routee for `msg`, `None` if there are no routees left.
+    fn pick(&self, msg: &M) -> Option<Handle<M>> {
+        let routees = self.inner.routees.lock().unwrap();
+        if routees.is_empty() {
+            return None;
+        }
+        let routee = match &self.inner.routing.0 {
+            Kind::RoundRobin => {
+                let next = self.inner.next.fetch_add(1, Ordering::Relaxed);
+                &routees[next % routees.len()]
+            }
+            Kind::Random => {
+                let mut hasher = RandomState::new().build_hasher();
+                hasher.write_usize(self.inner.next.fetch_add(1, Ordering::Relaxed));
+                &routees[hasher.finish() as usize % routees.len()]
+            }
+            Kind::LeastLoaded => routees.iter().min_by_key(|r| r.mailbox_len()).unwrap(),
+            // Rendezvous hashing: the routee scoring highest for the key wins.
+            Kind::ConsistentHash(key) => {
+                let key = key(msg);
+                routees
+                    .iter()
+                    .max_by_key(|routee| {
+                        let mut hasher = DefaultHasher::new();
+                        (key, routee.id()).hash(&mut hasher);
+                        hasher.finish()
+                    })
+                    .unwrap()
+            }
+            Kind::Broadcast(_) => unreachable!("broadcast sends to every routee"),
+        };
+        Some(routee.clone())
+    }
+
+    /// Remove routees whose actor has stopped.
+    fn prune(&self) {
+        let mut routees = self.inner.routees.lock().unwrap();
+        routees.retain(|routee| !routee.is_closed());
+    }
+}
+
+impl<M> Clone for Router<M> {
+    fn clone(&self) -> Self {
+        Router {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Builder, Context};
+    use std::convert::Infallible;
+
+    pub enum Message {
+        Work(u32),
+        Block(oneshot::Receiver<()>),
+        Done(Reply<Vec<u32>>),
+    }
+
+    pub struct Worker(Vec<u32>);
+
+    impl Actor for Worker {
+        type Msg = Message;
+        type Error = Infallible;
+        async fn recv(
+            &mut self,
+            msg: Self::Msg,
+            _ctx: &mut Context<Self>,
+        ) -> Result<(), Self::Error> {
+            match msg {
+                Message::Work(n) => self.0.push(n),
+                Message::Block(release) => {
+                    let _ = release.await;
+                }
+                Message::Done(reply) => reply.send(std::mem::take(&mut self.0)),
+            }
+            Ok(())
+        }
+    }
+
+    async fn done(routees: &[Handle<Message>]) -> Vec<Vec<u32>> {
+        let mut work = Vec::new();
+        for routee in routees {
+            work.push(routee.ask(Message::Done).await.unwrap());
+        }
+        work
+    }
+
+    #[tokio::test]
+    async fn test_round_robin() {
+        let router = Router::pool(Routing::round_robin(), 3, || Worker(Vec::new()));
+        for n in 0..6 {
+            router.send(Message::Work(n)).await.unwrap();
+        }
+        let work = done(&router.routees()).await;
+        assert_eq!(work, [vec![0, 3], vec![1, 4], vec![2, 5]]);
+    }
+
+    #[tokio::test]
+    async fn test_consistent_hash() {
+        let routing = Routing::consistent_hash(|msg: &Message| match msg {
+            Message::Work(n) => n % 4,
+            _ => 0,
+        });
+        let router = Router::pool(routing, 3, || Worker(Vec::new()));
+        for n in 0..16 {
+            router.try_send(Message::Work(n)).unwrap();
+        }
+        let work = done(&router.routees()).await;
+        for key in 0..4 {
+            let holders = work
+                .iter()
+                .filter(|w| w.iter().any(|n| n % 4 == key))
+                .count();
+            assert_eq!(holders, 1);
+        }
+
+        // A new routee only takes over keys, the others keep their routee.
+        let before: Vec<_> = (0..16).map(|n| owner(&work, n)).collect();
+        router.add(Handle::new(Worker(Vec::new())));
+        for n in 0..16 {
+            router.try_send(Message::Work(n)).unwrap();
+        }
+        let work = done(&router.routees()).await;
+        for (n, before) in before.into_iter().enumerate() {
+            let after = owner(&work, n as u32);
+            assert!(after == before || after == 3);
+        }
+    }
+
+    /// The index of the routee that handled `n`.
+    fn owner(work: &[Vec<u32>], n: u32) -> usize {
+        work.iter().position(|w| w.contains(&n)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_least_loaded_and_random() {
+        let busy = Handle::new(Worker(Vec::new()));
+        let idle = Handle::new(Worker(Vec::new()));
+        let (release, blocked) = oneshot::channel();
+        busy.send(Message::Block(blocked)).await.unwrap();
+        busy.send(Message::Work(0)).await.unwrap();
+        let router = Router::new(Routing::least_loaded(), [busy.clone(), idle.clone()]);
+        router.send(Message::Work(1)).await.unwrap();
+        release.send(()).unwrap();
+        assert_eq!(done(&router.routees()).await, [vec![0], vec![1]]);
+
+        let router = Router::new(Routing::random(), [busy, idle]);
+        for n in 0..10 {
+            router.send(Message::Work(n)).await.unwrap();
+        }
+        let work = done(&router.routees()).await;
+        assert_eq!(work.iter().map(Vec::len).sum::<usize>(), 10);
+    }
+
+    pub struct Counter(Arc<AtomicUsize>);
+
+    impl Actor for Counter {
+        type Msg = usize;
+        type Error = Infallible;
+        async fn recv(
+            &mut self,
+            msg: Self::Msg,
+            _ctx: &mut Context<Self>,
+        ) -> Result<(), Self::Error> {
+            self.0.fetch_add(msg, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_broadcast() {
+        let total = Arc::new(AtomicUsize::new(0));
+        let counter = total.clone();
+        let router = Router::pool(Routing::broadcast(), 3, move || Counter(counter.clone()));
+        router.send(2).await.unwrap();
+        let routees = router.routees();
+        router.stop();
+        for routee in routees {
+            routee.stopped().await;
+        }
+        assert_eq!(total.load(Ordering::Relaxed), 6);
+        assert_eq!(router.try_send(1), Err(TrySendError::Closed(1)));
+    }
+
+    #[tokio::test]
+    async fn test_resize_and_prune() {
+        let router = Router::pool(Routing::round_robin(), 2, || Worker(Vec::new()));
+        assert_eq!(router.resize(4), 4);
+        let removed = router.routees().pop().unwrap();
+        assert_eq!(router.resize(3), 3);
+        removed.stopped().await;
+
+        let (h, join) = Builder::new(Worker(Vec::new())).spawn_with_join();
+        router.add(h.clone());
+        h.stop();
+        join.await.unwrap();
+        for n in 0..4 {
+            router.send(Message::Work(n)).await.unwrap();
+        }
+        assert_eq!(router.len(), 3);
+
+        router.stop();
+        assert!(router.is_closed());
+        assert!(router.send(Message::Work(5)).await.is_err());
+    }
+}
@@ -0,0 +1,375 @@
+//! The task driving a spawned [`Actor`].
+
+use std::any::{type_name, Any};
+use std::future::{poll_fn, Future};
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::pin;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::task::Poll;
+
+use tokio::sync::{mpsc, watch};
+use tokio::task::AbortHandle;
+use tokio::time::{self, Duration};
+
+use crate::mailbox::{MailboxReceiver, WeakMailboxSender};
+use crate::monitor::Watchers;
+use crate::registry;
+use crate::system::Members;
+use crate::{Actor, ActorId, ActorSystem, Context, ErrorPolicy, ExitReason, Panic, PanicAction};
+
+/// Control signals delivered to the runner outside of the mailbox.
+pub(crate) enum Signal {
+    /// Close the mailbox, drain it and stop.
+    Stop,
+    /// Stop the current incarnation without closing the mailbox so a supervisor can restart it.
+    Restart,
+    /// A linked actor failed, fail as well.
+    Linked(ActorId),
+}
+
+/// Why a single run of an actor ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Exit {
+    /// The mailbox was closed and drained.
+    Normal,
+    /// A supervisor asked the actor to make way for a restart.
+    Restarted,
+    /// A handler or hook panicked with the given message.
+    Panicked(String),
+    /// The actor failed with the given error.
+    Failed(String),
+    /// A linked actor failed.
+    Linked(ActorId),
+}
+
+impl Exit {
+    /// Returns true if a supervisor should treat the exit as a failure.
+    pub(crate) fn is_abnormal(&self) -> bool {
+        matches!(self, Exit::Panicked(_) | Exit::Failed(_) | Exit::Linked(_))
+    }
+
+    /// The reason reported to watchers if the actor is not run again, `None` for a restart.
+    pub(crate) fn reason(&self) -> Option<ExitReason> {
+        match self {
+            Exit::Normal => Some(ExitReason::Normal),
+            Exit::Restarted => None,
+            Exit::Panicked(message) => Some(ExitReason::Panicked(message.clone())),
+            Exit::Failed(error) => Some(ExitReason::Failed(error.clone())),
+            Exit::Linked(id) => Some(ExitReason::Linked(*id)),
+        }
+    }
+}
+
+/// How long a stopping actor waits for its children to stop before aborting them.
+pub(crate) const CHILD_STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// State shared between every [`Handle`](crate::Handle) of an actor and its runner.
+pub(crate) struct ActorCell {
+    id: ActorId,
+    signals: mpsc::UnboundedSender<Signal>,
+    exit: watch::Sender<bool>,
+    error_policy: AtomicU8,
+    /// Names held in the registry, `None` once the actor has stopped.
+    pub(crate) names: Mutex<Option<Vec<String>>>,
+    pub(crate) watchers: Mutex<Watchers>,
+    /// The [`ActorSystem`](crate::ActorSystem) the actor belongs to, if any.
+    system: OnceLock<Weak<Mutex<Members>>>,
+    path: OnceLock<String>,
+    /// Actors spawned as children, stopped together with this actor.
+    children: Mutex<Vec<Weak<ActorCell>>>,
+    /// The task running the actor, aborted if it does not stop in time as a child.
+    task: OnceLock<AbortHandle>,
+}
+
+impl ActorCell {
+    pub(crate) fn new() -> (Arc<ActorCell>, Signals) {
+        let (signals, receiver) = mpsc::unbounded_channel();
+        let (exit, _) = watch::channel(false);
+        let cell = Arc::new(ActorCell {
+            id: ActorId::next(),
+            signals,
+            exit,
+            error_policy: AtomicU8::new(ErrorPolicy::Escalate as u8),
+            names: Mutex::new(Some(Vec::new())),
+            watchers: Mutex::new(Watchers::default()),
+            system: OnceLock::new(),
+            path: OnceLock::new(),
+            children: Mutex::new(Vec::new()),
+            task: OnceLock::new(),
+        });
+        let signals = Signals {
+            receiver,
+            cell: cell.clone(),
+        };
+        (cell, signals)
+    }
+
+    pub(crate) fn id(&self) -> ActorId {
+        self.id
+    }
+
+    /// Deliver a control signal, ignored if the actor has already stopped.
+    pub(crate) fn signal(&self, signal: Signal) {
+        let _ = self.signals.send(signal);
+    }
+
+    pub(crate) fn error_policy(&self) -> ErrorPolicy {
+        match self.error_policy.load(Ordering::Relaxed) {
+            p if p == ErrorPolicy::Ignore as u8 => ErrorPolicy::Ignore,
+            p if p == ErrorPolicy::Stop as u8 => ErrorPolicy::Stop,
+            _ => ErrorPolicy::Escalate,
+        }
+    }
+
+    pub(crate) fn set_error_policy(&self, policy: ErrorPolicy) {
+        self.error_policy.store(policy as u8, Ordering::Relaxed);
+    }
+
+    /// Remember why the latest run ended, reported to watchers once the actor has stopped.
+    pub(crate) fn record_exit(&self, exit: &Exit) {
+        if let Some(reason) = exit.reason() {
+            self.watchers.lock().unwrap().reason = Some(reason);
+        }
+    }
+
+    pub(crate) fn set_system(&self, system: Weak<Mutex<Members>>) {
+        let _ = self.system.set(system);
+    }
+
+    /// The system the actor belongs to, `None` if it has none or the system is gone.
+    pub(crate) fn system(&self) -> Option<ActorSystem> {
+        ActorSystem::upgrade(self.system.get()?)
+    }
+
+    /// The path of the actor, `/<id>` unless it was set when spawning.
+    pub(crate) fn path(&self) -> &str {
+        self.path.get_or_init(|| format!("/{}", self.id))
+    }
+
+    pub(crate) fn set_path(&self, path: String) {
+        let _ = self.path.set(path);
+    }
+
+    /// Track `child` so it is stopped with this actor.
+    pub(crate) fn adopt(&self, child: &Arc<ActorCell>) {
+        let mut children = self.children.lock().unwrap();
+        children.retain(|child| child.strong_count() > 0);
+        children.push(Arc::downgrade(child));
+    }
+
+    pub(crate) fn set_task(&self, task: AbortHandle) {
+        let _ = self.task.set(task);
+    }
+
+    /// Stop the children and wait until all of them have stopped, aborting the ones still
+    /// running after [`CHILD_STOP_TIMEOUT`].
+    async fn stop_children(&self) {
+        let children: Vec<_> = std::mem::take(&mut *self.children.lock().unwrap())
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect();
+        for child in &children {
+            child.signal(Signal::Stop);
+        }
+        let stopped = async {
+            for child in &children {
+                child.stopped().await;
+            }
+        };
+        if time::timeout(CHILD_STOP_TIMEOUT, stopped).await.is_ok() {
+            return;
+        }
+        for child in &children {
+            if let Some(task) = child.task.get() {
+                task.abort();
+            }
+        }
+        for child in &children {
+            child.stopped().await;
+        }
+    }
+
+    /// Returns true once the actor has stopped for good.
+    pub(crate) fn is_stopped(&self) -> bool {
+        *self.exit.borrow()
+    }
+
+    /// Wait until the actor has stopped for good.
+    pub(crate) async fn stopped(&self) {
+        let mut exit = self.exit.subscribe();
+        let _ = exit.wait_for(|stopped| *stopped).await;
+    }
+}
+
+/// Receiving end of the control signals.
+/// Dropping it marks the actor as stopped, whether it finished, panicked or was aborted.
+pub(crate) struct Signals {
+    receiver: mpsc::UnboundedReceiver<Signal>,
+    cell: Arc<ActorCell>,
+}
+
+impl Signals {
+    pub(crate) fn cell(&self) -> &Arc<ActorCell> {
+        &self.cell
+    }
+
+    pub(crate) async fn recv(&mut self) -> Option<Signal> {
+        self.receiver.recv().await
+    }
+
+    /// Drop the restarts sent to an incarnation that had already exited, so they do not
+    /// restart the next one as well. Other signals are kept.
+    pub(crate) fn discard_restarts(&mut self) {
+        let mut kept = Vec::new();
+        while let Ok(signal) = self.receiver.try_recv() {
+            if !matches!(signal, Signal::Restart) {
+                kept.push(signal);
+            }
+        }
+        for signal in kept {
+            self.cell.signal(signal);
+        }
+    }
+}
+
+impl Drop for Signals {
+    fn drop(&mut self) {
+        registry::release(&self.cell);
+        // Children are normally stopped by the runner, this covers an aborted task.
+        for child in self.cell.children.lock().unwrap().iter() {
+            if let Some(child) = child.upgrade() {
+                child.signal(Signal::Stop);
+            }
+        }
+        self.cell.notify_stopped();
+        self.cell.exit.send_replace(true);
+    }
+}
+
+/// Everything an actor receives: its mailbox and its control signals.
+/// The inbox outlives a single actor instance so a supervisor can restart it behind the same
+/// [`Handle`](crate::Handle).
+pub(crate) struct Inbox<M> {
+    pub(crate) mailbox: MailboxReceiver<M>,
+    pub(crate) signals: Signals,
+    /// Lets the actor address its own mailbox without keeping it open.
+    pub(crate) weak: WeakMailboxSender<M>,
+}
+
+/// Run the actor until its mailbox closes, it is signalled or it fails, then stop its
+/// children.
+/// The inbox is handed back so a supervisor can run a fresh instance on it.
+pub(crate) async fn run_actor<T: Actor>(
+    inbox: Inbox<T::Msg>,
+    actor: &mut T,
+) -> (Inbox<T::Msg>, Exit) {
+    let mut ctx = Context::new(inbox);
+    let exit = match run_loop(actor, &mut ctx).await {
+        Ok(exit) | Err(exit) => exit,
+    };
+    let cell = ctx.inbox.signals.cell().clone();
+    cell.stop_children().await;
+    cell.record_exit(&exit);
+    (ctx.into_inbox(), exit)
+}
+
+async fn run_loop<T: Actor>(actor: &mut T, ctx: &mut Context<T>) -> Result<Exit, Exit> {
+    let mut processed = 0;
+    hook(actor.started(ctx)).await?;
+    let exit = loop {
+        tokio::select! {
+            biased;
+            Some(signal) = ctx.inbox.signals.recv() => match signal {
+                Signal::Stop => {
+                    ctx.inbox.mailbox.close();
+                    break Exit::Normal;
+                }
+                Signal::Restart => break Exit::Restarted,
+                Signal::Linked(id) => break Exit::Linked(id),
+            },
+            msg = ctx.inbox.mailbox.recv() => match msg {
+                Some(msg) => {
+                    if !deliver(actor, ctx, msg, &mut processed).await? {
+                        ctx.inbox.mailbox.close();
+                        break Exit::Normal;
+                    }
+                }
+                None => break Exit::Normal,
+            },
+        }
+    };
+    hook(actor.stopping(ctx)).await?;
+    if exit == Exit::Normal {
+        // Messages queued before the mailbox was closed are still delivered.
+        while let Some(msg) = ctx.inbox.mailbox.recv().await {
+            deliver(actor, ctx, msg, &mut processed).await?;
+        }
+    }
+    hook(actor.stopped(ctx)).await?;
+    Ok(exit)
+}
+
+/// Handle a single message, applying the [`ErrorPolicy`] if the handler returns an error and
+/// asking [`Actor::on_panic`] what to do if it panics.
+/// Returns false if the actor should stop, or the exit if it should fail.
+async fn deliver<T: Actor>(
+    actor: &mut T,
+    ctx: &mut Context<T>,
+    msg: T::Msg,
+    processed: &mut u64,
+) -> Result<bool, Exit> {
+    let before = *processed;
+    *processed += 1;
+    let message = match catch_unwind(actor.recv(msg, ctx)).await {
+        Ok(Ok(())) => return Ok(true),
+        Ok(Err(error)) => {
+            return match ctx.inbox.signals.cell().error_policy() {
+                ErrorPolicy::Ignore => Ok(true),
+                ErrorPolicy::Stop => Ok(false),
+                ErrorPolicy::Escalate => Err(Exit::Failed(error.to_string())),
+            };
+        }
+        Err(message) => message,
+    };
+    let panic = Panic {
+        message,
+        msg_type: type_name::<T::Msg>(),
+        processed: before,
+    };
+    match panic::catch_unwind(AssertUnwindSafe(|| actor.on_panic(&panic))) {
+        Ok(PanicAction::Continue) => Ok(true),
+        Ok(PanicAction::Stop) => Ok(false),
+        Ok(PanicAction::Restart) => Err(Exit::Panicked(panic.message)),
+        Err(payload) => Err(Exit::Panicked(panic_message(payload))),
+    }
+}
+
+/// Poll a future, turning a panic into an error carrying the panic message.
+pub(crate) async fn catch_unwind<F: Future>(fut: F) -> Result<F::Output, String> {
+    let mut fut = pin!(fut);
+    poll_fn(
+        |cx| match panic::catch_unwind(AssertUnwindSafe(|| fut.as_mut().poll(cx))) {
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(payload) => Poll::Ready(Err(panic_message(payload))),
+        },
+    )
+    .await
+}
+
+/// Run a lifecycle hook, a panic fails the actor.
+async fn hook(fut: impl Future<Output = ()>) -> Result<(), Exit> {
+    catch_unwind(fut).await.map_err(Exit::Panicked)
+}
+
+/// Extract the message from a panic payload.
+pub(crate) fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(msg) => *msg,
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(msg) => msg.to_string(),
+            Err(_) => "unknown panic".to_string(),
+        },
+    }
+}
@@ -0,0 +1,537 @@
+//! Supervisors own child actors and restart them when they fail.
+//!
+//! A child is created from a factory so that a fresh instance can be built after a panic.
+//! The mailbox of a child survives its restarts, so the [`Handle`] returned when the child was
+//! added keeps working and callers do not notice the restart.
+//! Supervisors can be nested to build a supervision tree: a supervisor that exceeds its restart
+//! limit fails, and its parent restarts it together with all of its children.
+//!
+//! ```rust
+//! use miniactor::{Actor, Context, Strategy, Supervisor};
+//! use std::convert::Infallible;
+//!
+//! pub struct Worker;
+//!
+//! impl Actor for Worker {
+//!     type Msg = u32;
+//!     type Error = Infallible;
+//!
+//!     async fn recv(&mut self, msg: Self::Msg, _ctx: &mut Context<Self>) -> Result<(), Self::Error> {
+//!         assert!(msg != 0, "cannot handle zero");
+//!         Ok(())
+//!     }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let mut supervisor = Supervisor::new(Strategy::OneForOne);
+//!     let worker = supervisor.child(|| Worker);
+//!     let supervisor = supervisor.spawn();
+//!
+//!     // The panic restarts the worker, the handle keeps working.
+//!     worker.send(0).await.unwrap();
+//!     worker.send(1).await.unwrap();
+//!
+//!     supervisor.stop();
+//!     supervisor.join().await.unwrap();
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+
+use crate::mailbox::Config;
+use crate::runner::{panic_message, run_actor, ActorCell, Exit, Inbox, Signal, Signals};
+use crate::{registry, Actor, Handle, RestartLimitExceeded};
+
+/// Strategy decides which children are restarted when one of them fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Only the failed child is restarted.
+    OneForOne,
+    /// Every child is restarted when one of them fails.
+    OneForAll,
+    /// The failed child and every child added after it are restarted.
+    RestForOne,
+}
+
+/// Supervisor owns a group of child actors and restarts them according to its [`Strategy`].
+///
+/// A child is only restarted when it fails. A child that stops normally, because it was
+/// stopped with [`Handle::stop`] or its last [`Handle`] was dropped, is not restarted.
+/// If more than the allowed number of restarts happen within the configured period the
+/// supervisor gives up and stops all of its children.
+pub struct Supervisor {
+    strategy: Strategy,
+    max_restarts: usize,
+    within: Duration,
+    slots: Vec<Slot>,
+    cell: Arc<ActorCell>,
+    signals: Signals,
+}
+
+/// Handle to a running top level [`Supervisor`].
+pub struct SupervisorHandle {
+    cell: Arc<ActorCell>,
+    task: tokio::task::JoinHandle<Exit>,
+}
+
+impl SupervisorHandle {
+    /// Stop every child gracefully, then the supervisor itself.
+    pub fn stop(&self) {
+        self.cell.signal(Signal::Stop);
+    }
+
+    /// Wait until the supervisor has stopped.
+    /// Fails if the supervisor gave up because its children failed too often.
+    pub async fn join(self) -> Result<(), RestartLimitExceeded> {
+        match self.task.await {
+            Ok(Exit::Failed(_)) => Err(RestartLimitExceeded),
+            _ => Ok(()),
+        }
+    }
+}
+
+type ChildFuture = Pin<Box<dyn Future<Output = (Box<dyn Child>, Exit)> + Send>>;
+
+/// A restartable child, either an actor or a nested supervisor.
+trait Child: Send {
+    /// Run one incarnation of the child and hand it back once it exits.
+    fn run(self: Box<Self>) -> ChildFuture;
+}
+
+struct ActorChild<T: Actor, F> {
+    factory: F,
+    inbox: Inbox<T::Msg>,
+}
+
+impl<T, F> Child for ActorChild<T, F>
+where
+    T: Actor + 'static,
+    T::Msg: Send,
+    F: FnMut() -> T + Send + 'static,
+{
+    fn run(self: Box<Self>) -> ChildFuture {
+        let ActorChild {
+            mut factory,
+            mut inbox,
+        } = *self;
+        // Restarts are only sent to running children, any still queued are stale.
+        inbox.signals.discard_restarts();
+        Box::pin(async move {
+            let (inbox, exit) = match panic::catch_unwind(AssertUnwindSafe(&mut factory)) {
+                Ok(mut actor) => run_actor(inbox, &mut actor).await,
+                Err(payload) => (inbox, Exit::Panicked(panic_message(payload))),
+            };
+            (
+                Box::new(ActorChild { factory, inbox }) as Box<dyn Child>,
+                exit,
+            )
+        })
+    }
+}
+
+impl Child for Supervisor {
+    fn run(mut self: Box<Self>) -> ChildFuture {
+        Box::pin(async move {
+            let exit = self.supervise().await;
+            (self as Box<dyn Child>, exit)
+        })
+    }
+}
+
+struct Slot {
+    cell: Arc<ActorCell>,
+    state: State,
+}
+
+enum State {
+    /// The child is running.
+    Running,
+    /// The child was asked to exit and has not done so yet.
+    Exiting,
+    /// The child is not running and will be started again.
+    Waiting(Box<dyn Child>),
+    /// The child stopped normally and will not be restarted.
+    Done,
+}
+
+impl Supervisor {
+    /// Create a supervisor with the given [`Strategy`].
+    /// By default at most 3 restarts are allowed within 5 seconds.
+    pub fn new(strategy: Strategy) -> Self {
+        let (cell, signals) = ActorCell::new();
+        Supervisor {
+            strategy,
+            max_restarts: 3,
+            within: Duration::from_secs(5),
+            slots: Vec::new(),
+            cell,
+            signals,
+        }
+    }
+
+    /// Allow at most `max_restarts` restarts within the period `within` before giving up.
+    pub fn max_restarts(mut self, max_restarts: usize, within: Duration) -> Self {
+        self.max_restarts = max_restarts;
+        self.within = within;
+        self
+    }
+
+    /// Add a child actor built by `factory` and return its [`Handle`].
+    /// The factory is called again every time the child is restarted.
+    /// Messages sent before the supervisor is spawned are queued.
+    pub fn child<T, F>(&mut self, factory: F) -> Handle<T::Msg>
+    where
+        T: Actor + 'static,
+        T::Msg: Send,
+        F: FnMut() -> T + Send + 'static,
+    {
+        self.add_child(factory, Config::Unbounded)
+    }
+
+    /// Add a child actor with a mailbox holding at most `capacity` messages.
+    /// See [`Supervisor::child`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn bounded_child<T, F>(&mut self, factory: F, capacity: usize) -> Handle<T::Msg>
+    where
+        T: Actor + 'static,
+        T::Msg: Send,
+        F: FnMut() -> T + Send + 'static,
+    {
+        self.add_child(factory, Config::Bounded(capacity))
+    }
+
+    /// Add a nested supervisor as a child.
+    /// It is restarted with all of its children when it exceeds its own restart limit.
+    pub fn supervisor(&mut self, supervisor: Supervisor) {
+        self.slots.push(Slot {
+            cell: supervisor.cell.clone(),
+            state: State::Waiting(Box::new(supervisor)),
+        });
+    }
+
+    /// Start the supervisor and all of its children.
+    pub fn spawn(self) -> SupervisorHandle {
+        let cell = self.cell.clone();
+        let mut supervisor = self;
+        let task = tokio::spawn(async move { supervisor.supervise().await });
+        SupervisorHandle { cell, task }
+    }
+
+    fn add_child<T, F>(&mut self, factory: F, mailbox: Config<T::Msg>) -> Handle<T::Msg>
+    where
+        T: Actor + 'static,
+        T::Msg: Send,
+        F: FnMut() -> T + Send + 'static,
+    {
+        let (cell, signals) = ActorCell::new();
+        cell.set_path(format!("{}/{}", self.cell.path(), cell.id()));
+        let (sender, receiver) = mailbox.channel(cell.id());
+        let child = ActorChild {
+            factory,
+            inbox: Inbox {
+                mailbox: receiver,
+                signals,
+                weak: sender.downgrade(),
+            },
+        };
+        self.slots.push(Slot {
+            cell: cell.clone(),
+            state: State::Waiting(Box::new(child)),
+        });
+        let handle = Handle { sender, cell };
+        registry::bind_path(&handle).expect("paths made of actor ids are unique");
+        handle
+    }
+
+    /// Run every child and apply the restart strategy until all of them have stopped.
+    async fn supervise(&mut self) -> Exit {
+        self.signals.discard_restarts();
+        let mut running = JoinSet::new();
+        for index in 0..self.slots.len() {
+            self.start(index, &mut running);
+        }
+        let mut restarts = VecDeque::new();
+        let mut exit = None;
+        loop {
+            tokio::select! {
+                biased;
+                Some(signal) = self.signals.recv(), if exit.is_none() && !running.is_empty() => {
+                    exit = Some(match signal {
+                        Signal::Stop => {
+                            for slot in &mut self.slots {
+                                if let State::Waiting(_) = slot.state {
+                                    slot.state = State::Done;
+                                }
+                            }
+                            self.signal_running(0, None, || Signal::Stop);
+                            Exit::Normal
+                        }
+                        Signal::Restart => {
+                            self.signal_running(0, None, || Signal::Restart);
+                            Exit::Restarted
+                        }
+                        Signal::Linked(id) => {
+                            // Keep the mailboxes open in case a parent supervisor restarts us.
+                            self.signal_running(0, None, || Signal::Restart);
+                            Exit::Linked(id)
+                        }
+                    });
+                }
+                Some(joined) = running.join_next() => {
+                    // The task can only fail if the runtime is shutting down.
+                    let Ok((index, child, child_exit)) = joined else {
+                        continue;
+                    };
+                    let restarting = matches!(self.slots[index].state, State::Exiting);
+                    self.slots[index].state = if child_exit == Exit::Normal {
+                        State::Done
+                    } else {
+                        State::Waiting(child)
+                    };
+                    if exit.is_some() {
+                        continue;
+                    }
+                    if !child_exit.is_abnormal() || restarting {
+                        self.start_waiting(&mut running);
+                        continue;
+                    }
+
+                    let now = Instant::now();
+                    restarts.push_back(now);
+                    while restarts
+                        .front()
+                        .is_some_and(|at| now.duration_since(*at) > self.within)
+                    {
+                        restarts.pop_front();
+                    }
+                    if restarts.len() > self.max_restarts {
+                        // Keep the mailboxes open in case a parent supervisor restarts us.
+                        self.signal_running(0, None, || Signal::Restart);
+                        exit = Some(Exit::Failed("restart limit exceeded".to_string()));
+                        continue;
+                    }
+
+                    match self.strategy {
+                        Strategy::OneForOne => {}
+                        Strategy::OneForAll => self.signal_running(0, Some(index), || Signal::Restart),
+                        Strategy::RestForOne => self.signal_running(index + 1, None, || Signal::Restart),
+                    }
+                    self.start_waiting(&mut running);
+                }
+                else => break,
+            }
+        }
+        let exit = exit.unwrap_or(Exit::Normal);
+        self.cell.record_exit(&exit);
+        exit
+    }
+
+    fn start(&mut self, index: usize, running: &mut JoinSet<(usize, Box<dyn Child>, Exit)>) {
+        let State::Waiting(child) = std::mem::replace(&mut self.slots[index].state, State::Running)
+        else {
+            unreachable!("only waiting children can be started");
+        };
+        running.spawn(async move {
+            let (child, exit) = child.run().await;
+            (index, child, exit)
+        });
+    }
+
+    /// Start the waiting children in order once no child is still exiting.
+    fn start_waiting(&mut self, running: &mut JoinSet<(usize, Box<dyn Child>, Exit)>) {
+        let restarting = self
+            .slots
+            .iter()
+            .any(|slot| matches!(slot.state, State::Exiting));
+        if restarting {
+            return;
+        }
+        for index in 0..self.slots.len() {
+            if let State::Waiting(_) = self.slots[index].state {
+                self.start(index, running);
+            }
+        }
+    }
+
+    /// Signal the running children from index `from` on, except `skip`.
+    fn signal_running(&mut self, from: usize, skip: Option<usize>, signal: impl Fn() -> Signal) {
+        for (index, slot) in self.slots.iter_mut().enumerate().skip(from) {
+            if Some(index) != skip && matches!(slot.state, State::Running) {
+                slot.cell.signal(signal());
+                slot.state = State::Exiting;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Context, Reply};
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    pub enum Message {
+        Crash,
+        Incr,
+        Get(Reply<(usize, usize)>),
+    }
+
+    /// Counts messages and remembers which incarnation it is.
+    pub struct Counter {
+        count: usize,
+        generation: usize,
+    }
+
+    impl Counter {
+        fn factory(starts: Arc<AtomicUsize>) -> impl FnMut() -> Counter + Send + 'static {
+            move || Counter {
+                count: 0,
+                generation: starts.fetch_add(1, Ordering::SeqCst) + 1,
+            }
+        }
+    }
+
+    impl Actor for Counter {
+        type Msg = Message;
+        type Error = Infallible;
+        async fn recv(
+            &mut self,
+            msg: Self::Msg,
+            _ctx: &mut Context<Self>,
+        ) -> Result<(), Self::Error> {
+            match msg {
+                Message::Crash => panic!("test crash"),
+                Message::Incr => self.count += 1,
+                Message::Get(reply) => reply.send((self.count, self.generation)),
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_one_for_one() {
+        let (a_starts, b_starts) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let mut supervisor = Supervisor::new(Strategy::OneForOne);
+        let a = supervisor.child(Counter::factory(a_starts.clone()));
+        let b = supervisor.child(Counter::factory(b_starts.clone()));
+        let supervisor = supervisor.spawn();
+
+        a.send(Message::Incr).await.unwrap();
+        b.send(Message::Incr).await.unwrap();
+        a.send(Message::Crash).await.unwrap();
+        assert_eq!(a.ask(Message::Get).await, Ok((0, 2)));
+        assert_eq!(b.ask(Message::Get).await, Ok((1, 1)));
+
+        supervisor.stop();
+        supervisor.join().await.unwrap();
+        assert!(a.is_closed());
+    }
+
+    #[tokio::test]
+    async fn test_one_for_all() {
+        let (a_starts, b_starts) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let mut supervisor = Supervisor::new(Strategy::OneForAll);
+        let a = supervisor.child(Counter::factory(a_starts));
+        let b = supervisor.child(Counter::factory(b_starts));
+        let _supervisor = supervisor.spawn();
+
+        b.send(Message::Incr).await.unwrap();
+        assert_eq!(b.ask(Message::Get).await, Ok((1, 1)));
+        a.send(Message::Crash).await.unwrap();
+        assert_eq!(a.ask(Message::Get).await, Ok((0, 2)));
+        assert_eq!(b.ask(Message::Get).await, Ok((0, 2)));
+    }
+
+    #[tokio::test]
+    async fn test_one_for_all_simultaneous_crashes() {
+        let (a_starts, b_starts) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let mut supervisor = Supervisor::new(Strategy::OneForAll);
+        let a = supervisor.child(Counter::factory(a_starts));
+        let b = supervisor.child(Counter::factory(b_starts));
+        // Both children crash before the supervisor sees either exit, the restart it sends the
+        // second one must not also restart its next incarnation.
+        a.send(Message::Crash).await.unwrap();
+        b.send(Message::Crash).await.unwrap();
+        let _supervisor = supervisor.spawn();
+
+        assert_eq!(a.ask(Message::Get).await, Ok((0, 2)));
+        assert_eq!(b.ask(Message::Get).await, Ok((0, 2)));
+    }
+
+    #[tokio::test]
+    async fn test_rest_for_one() {
+        let starts: Vec<_> = (0..3).map(|_| Arc::new(AtomicUsize::new(0))).collect();
+        let mut supervisor = Supervisor::new(Strategy::RestForOne);
+        let handles: Vec<_> = starts
+            .iter()
+            .map(|s| supervisor.child(Counter::factory(s.clone())))
+            .collect();
+        let _supervisor = supervisor.spawn();
+
+        for h in &handles {
+            assert_eq!(h.ask(Message::Get).await, Ok((0, 1)));
+        }
+        handles[1].send(Message::Crash).await.unwrap();
+        assert_eq!(handles[1].ask(Message::Get).await, Ok((0, 2)));
+        assert_eq!(handles[2].ask(Message::Get).await, Ok((0, 2)));
+        assert_eq!(handles[0].ask(Message::Get).await, Ok((0, 1)));
+    }
+
+    #[tokio::test]
+    async fn test_restart_limit() {
+        let mut supervisor =
+            Supervisor::new(Strategy::OneForOne).max_restarts(1, Duration::from_secs(60));
+        let a = supervisor.child(Counter::factory(Arc::new(AtomicUsize::new(0))));
+        let supervisor = supervisor.spawn();
+
+        a.send(Message::Crash).await.unwrap();
+        a.send(Message::Crash).await.unwrap();
+        assert_eq!(supervisor.join().await, Err(RestartLimitExceeded));
+        a.stopped().await;
+        assert!(a.is_closed());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_restart_window() {
+        let starts = Arc::new(AtomicUsize::new(0));
+        let mut supervisor =
+            Supervisor::new(Strategy::OneForOne).max_restarts(1, Duration::from_secs(60));
+        let a = supervisor.child(Counter::factory(starts));
+        let _supervisor = supervisor.spawn();
+
+        a.send(Message::Crash).await.unwrap();
+        assert_eq!(a.ask(Message::Get).await, Ok((0, 2)));
+        // The first restart has left the window once it has passed.
+        tokio::time::advance(Duration::from_secs(61)).await;
+        a.send(Message::Crash).await.unwrap();
+        assert_eq!(a.ask(Message::Get).await, Ok((0, 3)));
+    }
+
+    #[tokio::test]
+    async fn test_nested_supervisor_restart() {
+        let starts = Arc::new(AtomicUsize::new(0));
+        let mut inner =
+            Supervisor::new(Strategy::OneForOne).max_restarts(0, Duration::from_secs(60));
+        let a = inner.child(Counter::factory(starts.clone()));
+        let mut outer = Supervisor::new(Strategy::OneForOne);
+        outer.supervisor(inner);
+        let _outer = outer.spawn();
+
+        a.send(Message::Incr).await.unwrap();
+        a.send(Message::Crash).await.unwrap();
+        assert_eq!(a.ask(Message::Get).await, Ok((0, 2)));
+    }
+}
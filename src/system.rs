@@ -0,0 +1,402 @@
+//! Actor systems owning a group of actors for a coordinated shutdown.
+//!
+//! Actors spawned with [`ActorSystem::spawn`] or [`Builder::system`] belong to the system, and
+//! so does every actor they spawn through their [`Context`](crate::Context).
+//! [`ActorSystem::shutdown`] stops all of them in phases:
+//!
+//! 1. the system stops accepting actors, one spawned from now on is stopped right away,
+//! 2. every actor is asked to stop: its mailbox is closed so new messages are refused,
+//! 3. every actor drains the messages queued so far and runs its [`Actor::stopping`] and
+//!    [`Actor::stopped`] hooks,
+//! 4. actors still running once the timeout has passed are aborted and reported in the
+//!    [`ShutdownReport`].
+//!
+//! ```rust
+//! use miniactor::{Actor, ActorSystem, Context};
+//! use std::convert::Infallible;
+//! use std::time::Duration;
+//!
+//! pub struct Writer;
+//!
+//! impl Actor for Writer {
+//!     type Msg = String;
+//!     type Error = Infallible;
+//!
+//!     async fn recv(&mut self, msg: Self::Msg, _ctx: &mut Context<Self>) -> Result<(), Self::Error> {
+//!         println!("{}", msg);
+//!         Ok(())
+//!     }
+//!
+//!     async fn stopped(&mut self, _ctx: &mut Context<Self>) {
+//!         println!("flushed");
+//!     }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let system = ActorSystem::new();
+//!     let writer = system.spawn(Writer);
+//!     writer.send("last words".to_string()).await.unwrap();
+//!
+//!     let report = system.shutdown(Duration::from_secs(5)).await;
+//!     assert!(report.is_clean());
+//!     assert!(writer.is_closed());
+//! }
+//! ```
+//!
+//! [`Builder::system`]: crate::Builder::system
+//! [`Actor::stopping`]: crate::Actor::stopping
+//! [`Actor::stopped`]: crate::Actor::stopped
+
+use std::collections::HashSet;
+use std::sync::{Arc, LazyLock, Mutex, Weak};
+use std::time::Duration;
+
+use tokio::task::AbortHandle;
+use tokio::time::{self, Instant};
+
+use crate::registry;
+use crate::runner::{ActorCell, Signal};
+use crate::{Actor, ActorId, Builder, Handle};
+
+/// Names of the systems alive, so every system has a root path of its own.
+static NAMES: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// ActorSystem owns the actors spawned through it so they can be shut down together.
+/// It can be cloned and passed around, clones share the same actors.
+#[derive(Clone)]
+pub struct ActorSystem {
+    inner: Arc<Mutex<Members>>,
+}
+
+pub(crate) struct Members {
+    name: String,
+    actors: Vec<Member>,
+    closed: bool,
+}
+
+struct Member {
+    cell: Arc<ActorCell>,
+    task: AbortHandle,
+    actor_type: &'static str,
+}
+
+impl ActorSystem {
+    /// Create a system named `system` without any actors.
+    /// If another system already has the name it is named `system-2`, `system-3` and so on.
+    pub fn new() -> Self {
+        let mut names = NAMES.lock().unwrap();
+        let name = (1..)
+            .map(|n| match n {
+                1 => "system".to_string(),
+                n => format!("system-{}", n),
+            })
+            .find(|name| !names.contains(name))
+            .unwrap();
+        names.insert(name.clone());
+        drop(names);
+        ActorSystem::create(name)
+    }
+
+    /// Create a system without any actors, the paths of its actors start with `/<name>`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is empty, contains a `/`, has the form `actor-<n>` of the names of
+    /// unnamed actors or is the name of another system that is still alive.
+    pub fn named(name: impl Into<String>) -> Self {
+        let name = name.into();
+        assert!(
+            registry::is_valid_name(&name),
+            "invalid system name '{}'",
+            name
+        );
+        let unique = NAMES.lock().unwrap().insert(name.clone());
+        assert!(unique, "a system named '{}' already exists", name);
+        ActorSystem::create(name)
+    }
+
+    fn create(name: String) -> Self {
+        ActorSystem {
+            inner: Arc::new(Mutex::new(Members {
+                name,
+                actors: Vec::new(),
+                closed: false,
+            })),
+        }
+    }
+
+    /// Spawn an [`Actor`] with an unbounded mailbox in this system and return its [`Handle`].
+    /// Use [`Builder::system`] for other options.
+    pub fn spawn<T: Actor>(&self, actor: T) -> Handle<T::Msg> {
+        Builder::new(actor).system(self).spawn()
+    }
+
+    /// The number of actors in the system that have not stopped yet.
+    pub fn len(&self) -> usize {
+        let mut members = self.inner.lock().unwrap();
+        members.prune();
+        members.actors.len()
+    }
+
+    /// Returns true if every actor in the system has stopped.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Stop every actor in the system, waiting at most `timeout` for them to drain their
+    /// mailboxes and run their stop hooks before aborting the rest.
+    /// See the [module documentation](self) for the phases.
+    pub async fn shutdown(&self, timeout: Duration) -> ShutdownReport {
+        let deadline = Instant::now() + timeout;
+        let running = {
+            let mut members = self.inner.lock().unwrap();
+            members.closed = true;
+            members.live()
+        };
+        for cell in &running {
+            cell.signal(Signal::Stop);
+        }
+
+        // Actors can still be spawned while the others stop, they are stopped right away and
+        // waited for as well.
+        loop {
+            let running = self.inner.lock().unwrap().live();
+            if running.is_empty() {
+                return ShutdownReport {
+                    timed_out: Vec::new(),
+                };
+            }
+            let stopped = async {
+                for cell in &running {
+                    cell.stopped().await;
+                }
+            };
+            if time::timeout_at(deadline, stopped).await.is_err() {
+                break;
+            }
+        }
+
+        let mut members = self.inner.lock().unwrap();
+        members.prune();
+        let timed_out = members
+            .actors
+            .iter()
+            .map(|member| {
+                member.task.abort();
+                Straggler {
+                    id: member.cell.id(),
+                    actor_type: member.actor_type,
+                }
+            })
+            .collect();
+        ShutdownReport { timed_out }
+    }
+
+    /// Add a spawned actor to the system, stopping it if the system is shutting down.
+    pub(crate) fn register(
+        &self,
+        cell: &Arc<ActorCell>,
+        task: AbortHandle,
+        actor_type: &'static str,
+    ) {
+        cell.set_system(Arc::downgrade(&self.inner));
+        let mut members = self.inner.lock().unwrap();
+        members.prune();
+        if members.closed {
+            cell.signal(Signal::Stop);
+        }
+        members.actors.push(Member {
+            cell: cell.clone(),
+            task,
+            actor_type,
+        });
+    }
+
+    /// The path the top level actors of the system are nested under.
+    pub(crate) fn root(&self) -> String {
+        format!("/{}", self.inner.lock().unwrap().name)
+    }
+
+    pub(crate) fn upgrade(members: &Weak<Mutex<Members>>) -> Option<ActorSystem> {
+        Some(ActorSystem {
+            inner: members.upgrade()?,
+        })
+    }
+}
+
+impl Default for ActorSystem {
+    fn default() -> Self {
+        ActorSystem::new()
+    }
+}
+
+impl Drop for Members {
+    fn drop(&mut self) {
+        NAMES.lock().unwrap().remove(&self.name);
+    }
+}
+
+impl Members {
+    /// Forget the actors that have stopped.
+    fn prune(&mut self) {
+        self.actors.retain(|member| !member.cell.is_stopped());
+    }
+
+    fn live(&mut self) -> Vec<Arc<ActorCell>> {
+        self.prune();
+        self.actors
+            .iter()
+            .map(|member| member.cell.clone())
+            .collect()
+    }
+}
+
+/// ShutdownReport is returned by [`ActorSystem::shutdown`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// The actors that did not stop in time and were aborted.
+    pub timed_out: Vec<Straggler>,
+}
+
+impl ShutdownReport {
+    /// Returns true if every actor stopped before the timeout.
+    pub fn is_clean(&self) -> bool {
+        self.timed_out.is_empty()
+    }
+}
+
+/// Straggler is an actor that did not stop within the shutdown timeout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Straggler {
+    /// The id of the actor.
+    pub id: ActorId,
+    /// The type name of the actor.
+    pub actor_type: &'static str,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Context, Reply};
+    use std::convert::Infallible;
+    use std::sync::Mutex;
+
+    pub enum Message {
+        Record,
+        /// Spawn a child actor in the same system.
+        Spawn(Reply<Handle<Message>>),
+        /// Never finish handling the message.
+        Hang,
+    }
+
+    pub struct Recorder(Arc<Mutex<Vec<&'static str>>>, &'static str);
+
+    impl Actor for Recorder {
+        type Msg = Message;
+        type Error = Infallible;
+        async fn recv(
+            &mut self,
+            msg: Self::Msg,
+            ctx: &mut Context<Self>,
+        ) -> Result<(), Self::Error> {
+            match msg {
+                Message::Record => self.0.lock().unwrap().push(self.1),
+                Message::Spawn(reply) => {
+                    reply.send(ctx.spawn(Recorder(self.0.clone(), "child")));
+                }
+                Message::Hang => std::future::pending().await,
+            }
+            Ok(())
+        }
+        async fn stopped(&mut self, _ctx: &mut Context<Self>) {
+            self.0.lock().unwrap().push("stopped");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shutdown() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let system = ActorSystem::new();
+        let parent = system.spawn(Recorder(log.clone(), "parent"));
+        let child = parent.ask(Message::Spawn).await.unwrap();
+        assert_eq!(system.len(), 2);
+
+        parent.send(Message::Record).await.unwrap();
+        child.send(Message::Record).await.unwrap();
+        let report = system.shutdown(Duration::from_secs(5)).await;
+        assert!(report.is_clean());
+        assert!(system.is_empty());
+        assert!(parent.is_closed() && child.is_closed());
+        let mut log = log.lock().unwrap().clone();
+        log.sort();
+        assert_eq!(log, ["child", "parent", "stopped", "stopped"]);
+
+        // Actors spawned after the shutdown stop right away.
+        let late = system.spawn(Recorder(Arc::default(), "late"));
+        late.stopped().await;
+    }
+
+    #[tokio::test]
+    async fn test_system_roots() {
+        let (first, second) = (ActorSystem::new(), ActorSystem::new());
+        let a = Builder::new(Recorder(Arc::default(), "a"))
+            .name("db")
+            .system(&first)
+            .spawn();
+        let b = Builder::new(Recorder(Arc::default(), "b"))
+            .name("db")
+            .system(&second)
+            .spawn();
+        assert_ne!(a.path(), b.path());
+
+        let named = ActorSystem::named("test_system_roots");
+        assert!(std::panic::catch_unwind(|| ActorSystem::named("test_system_roots")).is_err());
+        assert!(std::panic::catch_unwind(|| ActorSystem::named("actor-4")).is_err());
+        drop(named);
+        ActorSystem::named("test_system_roots");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_shutdown_timeout() {
+        let system = ActorSystem::new();
+        let stuck = Builder::new(Recorder(Arc::default(), "stuck"))
+            .system(&system)
+            .spawn();
+        let fine = system.spawn(Recorder(Arc::default(), "fine"));
+        stuck.send(Message::Hang).await.unwrap();
+        while stuck.mailbox_len() > 0 {
+            tokio::task::yield_now().await;
+        }
+
+        let report = system.shutdown(Duration::from_secs(1)).await;
+        assert_eq!(
+            report.timed_out,
+            [Straggler {
+                id: stuck.id(),
+                actor_type: std::any::type_name::<Recorder>(),
+            }]
+        );
+        assert!(fine.is_closed());
+        stuck.stopped().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stuck_child() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let parent = Handle::new(Recorder(log.clone(), "parent"));
+        let child = parent.ask(Message::Spawn).await.unwrap();
+        child.send(Message::Hang).await.unwrap();
+        while child.mailbox_len() > 0 {
+            tokio::task::yield_now().await;
+        }
+
+        // The parent gives up on the child and aborts it, its stop hook never runs.
+        let start = Instant::now();
+        parent.stop();
+        parent.stopped().await;
+        assert_eq!(start.elapsed(), crate::runner::CHILD_STOP_TIMEOUT);
+        assert!(child.is_closed());
+        assert_eq!(*log.lock().unwrap(), ["stopped"]);
+    }
+}